@@ -21,6 +21,17 @@ extern "C"
 		float x, y;
 	} Vector2;
 
+	typedef struct Quaternion
+	{
+		float x, y, z, w;
+	} Quaternion;
+
+	/* Column-major, translation in m[12], m[13], m[14]. */
+	typedef struct Matrix4
+	{
+		float m[16];
+	} Matrix4;
+
 	typedef struct Color
 	{
 		unsigned char x, y, z, w;
@@ -28,6 +39,8 @@ extern "C"
 
 	typedef struct XPSData XPSData;
 
+	typedef struct XPSImportParameters XPSImportParameters;
+
 	static const unsigned char XPS_ERROR_STREAM_NOT_OPENED = 0;
 	static const unsigned char XPS_ERROR_INVALID_HEADER = 1;
 	static const unsigned char XPS_ERROR_UNKNOWN = 2;
@@ -37,12 +50,93 @@ extern "C"
 	static const unsigned char XPS_ERROR_MESH_READ_ASCII = 6;
 	static const unsigned char XPS_ERROR_MESH_READ_BIN = 7;
 	static const unsigned char XPS_ERROR_NONE = 8;
+	static const unsigned char XPS_ERROR_FILE_NOT_WRITTEN = 9;
+	static const unsigned char XPS_ERROR_TEXTURE_NOT_DECODED = 10;
+	static const unsigned char XPS_ERROR_BONE_PALETTE_EXCEEDED = 11;
+	static const unsigned char XPS_ERROR_INVALID_ARGUMENT = 12;
 
 	static const unsigned char XPS_BONES_DEFAULT = 0;
 	static const unsigned char XPS_BONES_MECANIM = 1;
 
+	static const int XPS_AXIS_POSITIVE_X = 0;
+	static const int XPS_AXIS_NEGATIVE_X = 1;
+	static const int XPS_AXIS_POSITIVE_Y = 2;
+	static const int XPS_AXIS_NEGATIVE_Y = 3;
+	static const int XPS_AXIS_POSITIVE_Z = 4;
+	static const int XPS_AXIS_NEGATIVE_Z = 5;
+
+	static const int XPS_MATERIAL_BASE_COLOR = 0;
+	static const int XPS_MATERIAL_NORMAL = 1;
+	static const int XPS_MATERIAL_OCCLUSION = 2;
+	static const int XPS_MATERIAL_EMISSION = 3;
+	static const int XPS_MATERIAL_SPECULAR = 4;
+	static const int XPS_MATERIAL_DETAIL_MASK = 5;
+	static const int XPS_MATERIAL_DETAIL_NORMAL1 = 6;
+	static const int XPS_MATERIAL_DETAIL_NORMAL2 = 7;
+	static const int XPS_MATERIAL_ENVIRONMENT = 8;
+
+	static const int XPS_ALPHA_MODE_OPAQUE = 0;
+	static const int XPS_ALPHA_MODE_BLEND = 1;
+
+	static const int XPS_TEXTURE_SLOT_DIFFUSE = 0;
+	static const int XPS_TEXTURE_SLOT_LIGHTMAP = 1;
+	static const int XPS_TEXTURE_SLOT_BUMPMAP = 2;
+	static const int XPS_TEXTURE_SLOT_MASK = 3;
+	static const int XPS_TEXTURE_SLOT_BUMP1 = 4;
+	static const int XPS_TEXTURE_SLOT_BUMP2 = 5;
+	static const int XPS_TEXTURE_SLOT_SPECULAR = 6;
+	static const int XPS_TEXTURE_SLOT_ENVIRONMENT = 7;
+	static const int XPS_TEXTURE_SLOT_EMISSION = 8;
+	static const int XPS_TEXTURE_SLOT_EMISSION_MINI_MAP = 9;
+	static const int XPS_TEXTURE_SLOT_UNKNOWN = 10;
+
+	static const int XPS_WARNING_TEXTURE_COUNT_MISMATCH = 0;
+	static const int XPS_WARNING_UNKNOWN_RENDER_GROUP = 1;
+	static const int XPS_WARNING_INVALID_MESH_NAME = 2;
+	static const int XPS_WARNING_UNRESOLVED_CAMERA_TARGET = 3;
+	static const int XPS_WARNING_INVALID_AXIS_CONVERSION = 4;
+
+	static const int XPS_FLAG_BACKFACE_CULLING = 0;
+	static const int XPS_FLAG_ALWAYS_FORCE_CULLING = 1;
+	static const int XPS_FLAG_MODEL_CAST_SHADOWS = 2;
+	static const int XPS_FLAG_TANGENT_SPACE_RED = 3;
+	static const int XPS_FLAG_TANGENT_SPACE_GREEN = 4;
+	static const int XPS_FLAG_TANGENT_SPACE_BLUE = 5;
+	static const int XPS_FLAG_GLOSS = 6;
+	static const int XPS_FLAG_HAS_BONE_DIRECTIONS = 7;
+
 	XPS_API XPSData *xps_load_model(const char *filename, unsigned char bone_naming_format, int flip_uv, int reverse_winding);
 
+	XPS_API XPSImportParameters *xps_create_import_parameters();
+
+	XPS_API void xps_delete_import_parameters(XPSImportParameters *params);
+
+	XPS_API void xps_set_import_flip_uv(XPSImportParameters *params, int value);
+
+	XPS_API void xps_set_import_reverse_winding(XPSImportParameters *params, int value);
+
+	XPS_API void xps_set_import_optimize_vertex_cache(XPSImportParameters *params, int value);
+
+	XPS_API void xps_set_import_optimize_overdraw(XPSImportParameters *params, int value);
+
+	XPS_API void xps_set_import_normalize_weights(XPSImportParameters *params, int value, float threshold,
+	                                              int max_bone_influences);
+
+	XPS_API void xps_set_import_orient_bones(XPSImportParameters *params, int value);
+
+	// Load vertex data as one contiguous array per attribute, read with the
+	// xps_get_mesh_* buffer functions. The per-vertex getters read from the
+	// same arrays; xps_get_vertex_bone_weight_count then returns the padded
+	// number of slots.
+	XPS_API void xps_set_import_mesh_buffers(XPSImportParameters *params, int value);
+
+	XPS_API void xps_set_import_axes(XPSImportParameters *params, int up_axis, int forward_axis, int left_handed);
+
+	XPS_API void xps_set_import_scale(XPSImportParameters *params, float scale);
+
+	XPS_API XPSData *xps_load_model_with_parameters(const char *filename, unsigned char bone_naming_format,
+	                                                const XPSImportParameters *params);
+
 	XPS_API unsigned char xps_get_error(XPSData *model);
 
 	XPS_API void xps_delete_model(XPSData *model);
@@ -59,7 +153,14 @@ extern "C"
 	
 	XPS_API int xps_get_texture_id(XPSData *model, int mesh_index, int texture_index);
 	
-	XPS_API int xps_get_texture_filename(XPSData *model, int mesh_index, int texture_index);
+	XPS_API const char *xps_get_texture_filename(XPSData *model, int mesh_index, int texture_index);
+
+	XPS_API const char *xps_get_texture_path(XPSData *model, int mesh_index, int texture_index);
+
+	XPS_API int xps_get_texture_slot(XPSData *model, int mesh_index, int texture_index);
+
+	// Index of the first texture in an XPS_TEXTURE_SLOT_* slot, or -1.
+	XPS_API int xps_get_texture_index_by_slot(XPSData *model, int mesh_index, int slot);
 	
 	XPS_API int xps_get_texture_uv_layer(XPSData *model, int mesh_index, int texture_index);
 
@@ -69,8 +170,55 @@ extern "C"
 
 	XPS_API Vector3 xps_get_bone_position(XPSData *model, int index);
 
+	XPS_API Vector3 xps_get_bone_local_position(XPSData *model, int index);
+
+	XPS_API Matrix4 xps_get_bone_bind_matrix(XPSData *model, int index);
+
+	XPS_API Matrix4 xps_get_bone_inverse_bind_matrix(XPSData *model, int index);
+
+	// Bone index at position order_index when parents come before children, or -1.
+	XPS_API int xps_get_sorted_bone_index(XPSData *model, int order_index);
+
+	XPS_API Quaternion xps_get_bone_rest_rotation(XPSData *model, int index);
+
+	XPS_API Quaternion xps_get_bone_local_rotation(XPSData *model, int index);
+
 	XPS_API const char *xps_get_mesh_name(XPSData *model, int mesh_index);
 
+	// The encoded name as stored in the file, including render group and parameters.
+	XPS_API const char *xps_get_mesh_full_name(XPSData *model, int mesh_index);
+
+	// Item name parsed from the mesh name; meshes of one optional item share it.
+	XPS_API const char *xps_get_mesh_item_name(XPSData *model, int mesh_index);
+
+	XPS_API int xps_get_mesh_is_optional(XPSData *model, int mesh_index);
+
+	XPS_API int xps_get_mesh_visible_by_default(XPSData *model, int mesh_index);
+
+	// Camera named in the mesh name; the name is NULL when the mesh has none.
+	// Target bones are resolved against the bone names in the file, -1 if
+	// no bone matches.
+	XPS_API int xps_get_mesh_has_camera(XPSData *model, int mesh_index);
+
+	XPS_API const char *xps_get_mesh_camera_name(XPSData *model, int mesh_index);
+
+	XPS_API int xps_get_mesh_camera_target_count(XPSData *model, int mesh_index);
+
+	XPS_API const char *xps_get_mesh_camera_target_name(XPSData *model, int mesh_index, int target_index);
+
+	XPS_API int xps_get_mesh_camera_target_bone(XPSData *model, int mesh_index, int target_index);
+
+	// Optional items in order of first appearance.
+	XPS_API int xps_get_optional_item_count(XPSData *model);
+
+	XPS_API const char *xps_get_optional_item_name(XPSData *model, int item_index);
+
+	XPS_API int xps_get_optional_item_visible_by_default(XPSData *model, int item_index);
+
+	XPS_API int xps_get_optional_item_mesh_count(XPSData *model, int item_index);
+
+	XPS_API int xps_get_optional_item_mesh_index(XPSData *model, int item_index, int index);
+
 	XPS_API int xps_get_uv_layers(XPSData *model, int mesh_index);
 
 	XPS_API int xps_get_vertex_count(XPSData *model, int mesh_index);
@@ -81,8 +229,14 @@ extern "C"
 
 	XPS_API Color xps_get_vertex_color(XPSData *model, int mesh_index, int vertex_index);
 
+	// Any layer below xps_get_uv_layers; other layers read as (0, 0).
 	XPS_API Vector2 xps_get_vertex_uv(XPSData *model, int mesh_index, int vertex_index, int layer_id);
 
+	// Four, including meshes without bones, unless the file stores a count per
+	// vertex (version 3 and later). Reading past the count gives bone 0 with
+	// weight 0.
+	XPS_API int xps_get_vertex_bone_weight_count(XPSData *model, int mesh_index, int vertex_index);
+
 	XPS_API int xps_get_vertex_bone_index(XPSData *model, int mesh_index, int vertex_index, int weight_id);
 
 	XPS_API float xps_get_vertex_bone_weight(XPSData *model, int mesh_index, int vertex_index, int weight_id);
@@ -97,10 +251,102 @@ extern "C"
 
 	XPS_API int xps_get_render_group_spec1_rep(XPSData *model, int mesh_index);
 
+	XPS_API int xps_get_render_group_number(XPSData *model, int mesh_index);
+
+	// 0 when the render group number has no definition and the default layout is used.
+	XPS_API int xps_get_render_group_known(XPSData *model, int mesh_index);
+
 	XPS_API int xps_get_render_group_texture_count(XPSData *model, int mesh_index);
 
 	XPS_API const char * xps_get_render_group_texture_type(XPSData *model, int mesh_index, int texture_type_index);
 
+	XPS_API Vector3 xps_get_mesh_bounds_min(XPSData *model, int mesh_index);
+
+	XPS_API Vector3 xps_get_mesh_bounds_max(XPSData *model, int mesh_index);
+
+	XPS_API Vector3 xps_get_mesh_bounding_sphere_center(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_mesh_bounding_sphere_radius(XPSData *model, int mesh_index);
+
+	XPS_API Vector3 xps_get_model_bounds_min(XPSData *model);
+
+	XPS_API Vector3 xps_get_model_bounds_max(XPSData *model);
+
+	XPS_API Vector3 xps_get_model_bounding_sphere_center(XPSData *model);
+
+	XPS_API float xps_get_model_bounding_sphere_radius(XPSData *model);
+
+	XPS_API int xps_has_bone_bounds(XPSData *model, int index, float threshold);
+
+	XPS_API Vector3 xps_get_bone_bounds_min(XPSData *model, int index, float threshold);
+
+	XPS_API Vector3 xps_get_bone_bounds_max(XPSData *model, int index, float threshold);
+
+	XPS_API int xps_get_mesh_max_influences(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_mesh_specular_amount(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_mesh_bump1_uv_scale(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_mesh_bump2_uv_scale(XPSData *model, int mesh_index);
+
+	// Index into the mesh textures for an XPS_MATERIAL_* slot, or -1 if the slot is unused.
+	XPS_API int xps_get_material_texture(XPSData *model, int mesh_index, int slot);
+
+	XPS_API float xps_get_material_texture_tiling(XPSData *model, int mesh_index, int slot);
+
+	XPS_API int xps_get_material_alpha_mode(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_material_metallic(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_material_roughness(XPSData *model, int mesh_index);
+
+	XPS_API float xps_get_material_specular_intensity(XPSData *model, int mesh_index);
+
+	XPS_API Vector3 xps_get_material_emissive_factor(XPSData *model, int mesh_index);
+
+	XPS_API int xps_get_warning_count(XPSData *model);
+
+	XPS_API int xps_get_warning_kind(XPSData *model, int index);
+
+	// -1 when the warning is not about a single mesh.
+	XPS_API int xps_get_warning_mesh_index(XPSData *model, int index);
+
+	XPS_API const char *xps_get_warning_message(XPSData *model, int index);
+
+	XPS_API int xps_get_header_version_mayor(XPSData *model);
+
+	XPS_API int xps_get_header_version_minor(XPSData *model);
+
+	// Value of an XPS_FLAG_* key, falling back to the XPS default when the file
+	// does not set it; -1 for an unknown key the file does not contain.
+	XPS_API int xps_get_header_flag(XPSData *model, int key);
+
+	// Buffers of a mesh loaded with xps_set_import_mesh_buffers; pointers are NULL
+	// otherwise and stay valid until the model is deleted. Positions and normals
+	// hold 3 floats per vertex, colours 4 bytes, each UV layer 2 floats.
+	XPS_API int xps_get_mesh_has_buffers(XPSData *model, int mesh_index);
+
+	XPS_API const float *xps_get_mesh_positions(XPSData *model, int mesh_index);
+
+	XPS_API const float *xps_get_mesh_normals(XPSData *model, int mesh_index);
+
+	XPS_API const unsigned char *xps_get_mesh_colors(XPSData *model, int mesh_index);
+
+	XPS_API const float *xps_get_mesh_uvs(XPSData *model, int mesh_index, int layer_id);
+
+	// Joints and weights hold xps_get_mesh_influences entries per vertex.
+	XPS_API int xps_get_mesh_influences(XPSData *model, int mesh_index);
+
+	XPS_API const short *xps_get_mesh_joints(XPSData *model, int mesh_index);
+
+	XPS_API const float *xps_get_mesh_weights(XPSData *model, int mesh_index);
+
+	// 2 for 16-bit indices, 4 for 32-bit; xps_get_mesh_index_count gives the count.
+	XPS_API int xps_get_mesh_index_size(XPSData *model, int mesh_index);
+
+	XPS_API const void *xps_get_mesh_indices(XPSData *model, int mesh_index);
+
 #ifdef __cplusplus
 }
 #endif
@@ -1,10 +1,16 @@
 use super::error_types::XpsError;
 use super::file_input::FileStream;
+use super::material::RenderGroup;
+use super::mesh_buffers::MeshBuffers;
+use super::skinning;
+use super::texture_resolver;
 use std::collections::HashMap;
 use std::ffi::CString;
-use std::path::Path;
 
-use super::types::{Bone, BonePose, BoneWeight, Data, Header, Mesh, Texture, Vertex};
+use super::types::{
+    Bone, BonePose, BoneWeight, CameraTarget, Data, Header, Mesh, MeshItem, RenderParameters,
+    Texture, Vertex,
+};
 
 pub fn split_values(line: &String) -> Vec<String> {
     line.replace("#", " ")
@@ -65,24 +71,19 @@ fn read_values(file: &mut FileStream, _: usize) -> Vec<String> {
     values
 }
 
-pub fn read_bone_weight(file: &mut FileStream) -> [f32; 4] {
-    let values = read_values(file, 4);
-    [
-        get_float(&values[0]),
-        get_float(&values[1]),
-        get_float(&values[2]),
-        get_float(&values[3]),
-    ]
+// Bone ids and weights take one line each, with as many values as the
+// vertex has influences.
+fn read_line_values(file: &mut FileStream) -> Vec<String> {
+    let line = file.read_line_trim();
+    split_values(&ignore_string_comment(&line))
 }
 
-pub fn read_bone_ids(file: &mut FileStream) -> [i32; 4] {
-    let values = read_values(file, 4);
-    [
-        get_int(&values[0]),
-        get_int(&values[1]),
-        get_int(&values[2]),
-        get_int(&values[3]),
-    ]
+pub fn read_bone_weight(file: &mut FileStream) -> Vec<f32> {
+    read_line_values(file).iter().map(get_float).collect()
+}
+
+pub fn read_bone_ids(file: &mut FileStream) -> Vec<i32> {
+    read_line_values(file).iter().map(get_int).collect()
 }
 
 pub fn read_int4(file: &mut FileStream) -> (i32, i32, i32, i32) {
@@ -116,6 +117,7 @@ pub fn read_bones(file: &mut FileStream) -> Vec<Bone> {
             name: CString::new(name).unwrap_or(CString::new("").unwrap()),
             co: read_xyz(file),
             parent_id: parent as i16,
+            rest_rotation: super::math::QUAT_IDENTITY,
         })
     }
     bones
@@ -133,46 +135,45 @@ pub fn read_meshes(
         if mesh_name.len() == 0 {
             mesh_name = "xxx".to_string();
         }
+        let parser = super::mesh_name_parser::MeshNameParser::new(&mesh_name);
+        let render_group = RenderGroup::new(parser.get_render_group_number());
         let uv_layer_count = file.read_int() as usize;
         let mut textures = vec![];
         let texture_count = file.read_int();
         for tex_id in 0..texture_count {
-            let texture_file: String = {
-                let filename = file.read_string();
-                match Path::new(&filename).parent() {
-                    Some(x) => {
-                        if let Some(y) = x.to_str() {
-                            filename.replace(y, "").replace("\\", "/").replace("/", "")
-                        } else {
-                            return Err(XpsError::PathToStr);
-                        }
-                    }
-                    None => return Err(XpsError::PathGetParent),
-                }
-            };
+            let texture_path = file.read_string();
+            let texture_file = texture_resolver::file_name(&texture_path).to_string();
             let uv_layer_id = file.read_int();
             textures.push(Texture {
                 id: tex_id as u16,
                 file: CString::new(texture_file).unwrap_or(CString::new("").unwrap()),
+                path: CString::new(texture_path).unwrap_or(CString::new("").unwrap()),
                 uv_layer: uv_layer_id as u16,
+                slot: render_group.texture_slot(tex_id as usize),
             });
         }
 
         let vertex_count = file.read_int() as usize;
 
-        let mut vertex = vec![Vertex::default(); vertex_count];
-        for vtx in 0..vertex_count {
-            vertex[vtx].position = read_xyz(file);
-            vertex[vtx].normal = read_xyz(file);
+        let mut vertex = vec![];
+        let mut buffers = if params.mesh_buffers {
+            Some(MeshBuffers::new(uv_layer_count))
+        } else {
+            None
+        };
+        for _ in 0..vertex_count {
+            let position = read_xyz(file);
+            let normal = read_xyz(file);
             let vertex_color = read_int4(file);
-            vertex[vtx].color = [
+            let color = [
                 vertex_color.0 as u8,
                 vertex_color.1 as u8,
                 vertex_color.2 as u8,
                 vertex_color.3 as u8,
             ];
-            for x in 0..uv_layer_count {
-                vertex[vtx].uv[x] = {
+            let mut uv = vec![];
+            for _ in 0..uv_layer_count {
+                uv.push({
                     let line = file.read_line_trim();
                     let values = split_values(&line);
                     [get_float(&values[0]), {
@@ -183,18 +184,31 @@ pub fn read_meshes(
                             v
                         }
                     }]
-                };
+                });
             }
 
+            let mut bone_weights = vec![BoneWeight::default(); skinning::MAX_INFLUENCES];
             if has_bones {
                 let bone_idx = read_bone_ids(file);
                 let bone_weight = read_bone_weight(file);
-                for idx in 0..4 {
-                    vertex[vtx].bone_weights[idx] = BoneWeight {
-                        id: bone_idx[idx] as i16,
-                        weight: bone_weight[idx],
-                    };
-                }
+                let weight_count = bone_idx.len().max(bone_weight.len());
+                bone_weights = (0..weight_count)
+                    .map(|idx| BoneWeight {
+                        id: bone_idx.get(idx).cloned().unwrap_or(0) as i16,
+                        weight: bone_weight.get(idx).cloned().unwrap_or(0_f32),
+                    })
+                    .collect();
+            }
+            match buffers.as_mut() {
+                Some(x) => x.push_vertex(position, normal, color, &uv, &bone_weights),
+                None => vertex.push(Vertex {
+                    position,
+                    normal,
+                    color,
+                    uv,
+                    bone_weights,
+                    merged: false,
+                }),
             }
         }
         let mut faces = vec![];
@@ -210,17 +224,18 @@ pub fn read_meshes(
                 faces.push(tri_idx.2 as u32);
             }
         }
-        let parser = super::mesh_name_parser::MeshNameParser::new(&mesh_name);
-
         meshes.push(Mesh {
             name: CString::new(parser.mesh_name.clone()).unwrap_or(CString::new("").unwrap()),
+            full_name: CString::new(parser.full_name.clone()).unwrap_or_default(),
             textures: textures,
             vertices: vertex,
             faces: faces,
             uv_count: uv_layer_count as u16,
-            render_group: {
-                super::material::RenderGroup::new(parser.get_render_group_number())
-            },
+            render_group,
+            render_parameters: RenderParameters::from_parser(&parser),
+            item: MeshItem::from_parser(&parser),
+            camera: CameraTarget::from_parser(&parser),
+            buffers,
         });
     }
     Ok(meshes)
@@ -279,6 +294,10 @@ pub fn read_xps_model(
                 header: Header::default(),
                 bones: bones,
                 meshes: meshes,
+                optional_items: vec![],
+                bone_order: vec![],
+                parameters: params,
+                warnings: vec![],
                 error: XpsError::None,
             });
         }
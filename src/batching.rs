@@ -0,0 +1,284 @@
+use super::error_types::XpsError;
+use super::types::{Data, Mesh, Vertex};
+use std::collections::{BTreeSet, HashMap};
+
+pub const DEFAULT_BONE_PALETTE: usize = 64;
+
+pub struct SubMesh {
+  pub mesh: Mesh,
+  pub bone_remap: Vec<i16>,
+}
+
+fn can_merge(a: &Mesh, b: &Mesh) -> bool {
+  a.textures == b.textures
+    && a.render_group == b.render_group
+    && a.render_parameters == b.render_parameters
+    && a.item == b.item
+    && a.camera == b.camera
+}
+
+// Buffered meshes are merged as vertices and buffered again afterwards.
+pub fn merge_meshes(meshes: &[Mesh]) -> Vec<Mesh> {
+  let unpacked: Vec<_> = meshes.iter().map(|x| x.unpacked()).collect();
+  let mut groups: Vec<Vec<usize>> = vec![];
+  for (index, mesh) in unpacked.iter().enumerate() {
+    match groups.iter_mut().find(|x| can_merge(&unpacked[x[0]], mesh)) {
+      Some(group) => group.push(index),
+      None => groups.push(vec![index]),
+    }
+  }
+  let buffered = meshes.iter().any(|x| x.buffers.is_some());
+  groups
+    .iter()
+    .map(|group| {
+      let mut merged = unpacked[group[0]].clone().into_owned();
+      merged.uv_count = group
+        .iter()
+        .map(|x| unpacked[*x].uv_count)
+        .max()
+        .unwrap_or(0);
+      for index in &group[1..] {
+        let mesh = &unpacked[*index];
+        let offset = merged.vertices.len() as u32;
+        merged.vertices.extend_from_slice(&mesh.vertices);
+        merged.faces.extend(mesh.faces.iter().map(|x| x + offset));
+      }
+      // Meshes with fewer layers get zero UVs for the missing ones.
+      if group.len() > 1 {
+        for vertex in merged.vertices.iter_mut() {
+          vertex.uv.resize(merged.uv_count as usize, [0_f32; 2]);
+        }
+      }
+      if buffered {
+        merged.pack_buffers();
+      }
+      merged
+    })
+    .collect()
+}
+
+pub fn merge_model(data: &mut Data) {
+  data.meshes = merge_meshes(&data.meshes);
+  data.update_optional_items();
+}
+
+fn extract(mesh: &Mesh, triangles: &[usize]) -> Mesh {
+  let mut remap: HashMap<u32, u32> = HashMap::new();
+  let mut vertices: Vec<Vertex> = vec![];
+  let mut faces = Vec::with_capacity(triangles.len() * 3);
+  for triangle in triangles {
+    for index in &mesh.faces[triangle * 3..triangle * 3 + 3] {
+      let new_index = *remap.entry(*index).or_insert_with(|| {
+        vertices.push(mesh.vertices[*index as usize].clone());
+        vertices.len() as u32 - 1
+      });
+      faces.push(new_index);
+    }
+  }
+  Mesh {
+    name: mesh.name.clone(),
+    full_name: mesh.full_name.clone(),
+    textures: mesh.textures.clone(),
+    vertices,
+    faces,
+    uv_count: mesh.uv_count,
+    render_group: mesh.render_group.clone(),
+    render_parameters: mesh.render_parameters,
+    item: mesh.item.clone(),
+    camera: mesh.camera.clone(),
+    buffers: None,
+  }
+}
+
+fn valid_triangles(mesh: &Mesh) -> impl Iterator<Item = usize> + '_ {
+  mesh
+    .faces
+    .chunks_exact(3)
+    .enumerate()
+    .filter(move |(_, face)| face.iter().all(|x| (*x as usize) < mesh.vertices.len()))
+    .map(|(index, _)| index)
+}
+
+fn find(parents: &mut [usize], x: usize) -> usize {
+  let mut root = x;
+  while parents[root] != root {
+    root = parents[root];
+  }
+  let mut node = x;
+  while parents[node] != root {
+    let next = parents[node];
+    parents[node] = root;
+    node = next;
+  }
+  root
+}
+
+pub fn split_connected(mesh: &Mesh) -> Vec<Mesh> {
+  let buffered = mesh.buffers.is_some();
+  let mesh = &*mesh.unpacked();
+  let mut parents: Vec<usize> = (0..mesh.vertices.len()).collect();
+  let triangles: Vec<usize> = valid_triangles(mesh).collect();
+  for triangle in &triangles {
+    let face = &mesh.faces[triangle * 3..triangle * 3 + 3];
+    let a = find(&mut parents, face[0] as usize);
+    for index in &face[1..] {
+      let b = find(&mut parents, *index as usize);
+      parents[b] = a;
+    }
+  }
+  let mut components: Vec<Vec<usize>> = vec![];
+  let mut component_of_root: HashMap<usize, usize> = HashMap::new();
+  for triangle in triangles {
+    let root = find(&mut parents, mesh.faces[triangle * 3] as usize);
+    let component = *component_of_root.entry(root).or_insert_with(|| {
+      components.push(vec![]);
+      components.len() - 1
+    });
+    components[component].push(triangle);
+  }
+  components
+    .iter()
+    .map(|x| {
+      let mut component = extract(mesh, x);
+      if buffered {
+        component.pack_buffers();
+      }
+      component
+    })
+    .collect()
+}
+
+fn triangle_bones(mesh: &Mesh, triangle: usize) -> BTreeSet<i16> {
+  mesh.faces[triangle * 3..triangle * 3 + 3]
+    .iter()
+    .flat_map(|x| mesh.vertices[*x as usize].bone_weights.iter())
+    .filter(|x| x.weight > 0_f32)
+    .map(|x| x.id)
+    .collect()
+}
+
+fn to_submesh(mesh: &Mesh, triangles: &[usize], palette: &BTreeSet<i16>) -> SubMesh {
+  let mut submesh = extract(mesh, triangles);
+  let bone_remap: Vec<i16> = palette.iter().cloned().collect();
+  for vertex in submesh.vertices.iter_mut() {
+    for bone_weight in vertex.bone_weights.iter_mut() {
+      match bone_remap.iter().position(|x| *x == bone_weight.id) {
+        Some(local) if bone_weight.weight > 0_f32 => bone_weight.id = local as i16,
+        _ => {
+          bone_weight.id = 0;
+          bone_weight.weight = 0_f32;
+        }
+      }
+    }
+  }
+  SubMesh {
+    mesh: submesh,
+    bone_remap,
+  }
+}
+
+// Fails when a single triangle is weighted to more bones than the palette
+// holds, since no split can bring that triangle under the limit.
+pub fn split_by_bones(mesh: &Mesh, max_bones: usize) -> Result<Vec<SubMesh>, XpsError> {
+  let buffered = mesh.buffers.is_some();
+  let mesh = &*mesh.unpacked();
+  let mut submeshes = vec![];
+  let mut triangles: Vec<usize> = vec![];
+  let mut palette: BTreeSet<i16> = BTreeSet::new();
+  for triangle in valid_triangles(mesh) {
+    let bones = triangle_bones(mesh, triangle);
+    if bones.len() > max_bones {
+      return Err(XpsError::BonePaletteExceeded);
+    }
+    let extra = bones.difference(&palette).count();
+    if !triangles.is_empty() && palette.len() + extra > max_bones {
+      submeshes.push(to_submesh(mesh, &triangles, &palette));
+      triangles.clear();
+      palette.clear();
+    }
+    palette.extend(bones);
+    triangles.push(triangle);
+  }
+  if !triangles.is_empty() {
+    submeshes.push(to_submesh(mesh, &triangles, &palette));
+  }
+  if buffered {
+    for submesh in submeshes.iter_mut() {
+      submesh.mesh.pack_buffers();
+    }
+  }
+  Ok(submeshes)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+  use crate::types::BoneWeight;
+
+  fn triangle(offset: f32, bones: [i16; 3]) -> Mesh {
+    let vertices = bones
+      .iter()
+      .enumerate()
+      .map(|(index, bone)| {
+        let mut vertex = test_util::vertex(
+          [offset + index as f32, (index % 2) as f32, 0_f32],
+          [0_f32, 0_f32, 1_f32],
+        );
+        vertex.bone_weights = vec![BoneWeight {
+          id: *bone,
+          weight: 1_f32,
+        }];
+        vertex
+      })
+      .collect();
+    test_util::mesh(vertices, vec![0, 1, 2])
+  }
+
+  #[test]
+  fn merging_pads_missing_uv_layers() {
+    let mut two_layers = triangle(0_f32, [0, 0, 0]);
+    two_layers.uv_count = 2;
+    for vertex in two_layers.vertices.iter_mut() {
+      vertex.uv.push([0.5, 0.5]);
+    }
+    let merged = merge_meshes(&[triangle(0_f32, [0, 0, 0]), two_layers]);
+    assert_eq!(merged.len(), 1);
+    assert_eq!(merged[0].uv_count, 2);
+    assert_eq!(merged[0].vertices.len(), 6);
+    assert_eq!(merged[0].faces, [0, 1, 2, 3, 4, 5]);
+    assert!(merged[0].vertices.iter().all(|x| x.uv.len() == 2));
+    assert_eq!(merged[0].vertices[0].uv[1], [0_f32; 2]);
+    assert_eq!(merged[0].vertices[3].uv[1], [0.5, 0.5]);
+
+    let mut other = triangle(0_f32, [0, 0, 0]);
+    other.render_group = crate::material::RenderGroup::new(2);
+    assert_eq!(merge_meshes(&[triangle(0_f32, [0, 0, 0]), other]).len(), 2);
+  }
+
+  #[test]
+  fn splits_by_component_and_bone_palette() {
+    let merged = merge_meshes(&[triangle(0_f32, [0, 1, 1]), triangle(5_f32, [2, 3, 3])]);
+    assert_eq!(merged.len(), 1);
+    let components = split_connected(&merged[0]);
+    assert_eq!(components.len(), 2);
+    assert!(components
+      .iter()
+      .all(|x| x.vertices.len() == 3 && x.faces == [0, 1, 2]));
+
+    let submeshes = split_by_bones(&merged[0], 2).unwrap();
+    assert_eq!(submeshes.len(), 2);
+    assert_eq!(submeshes[0].bone_remap, [0, 1]);
+    assert_eq!(submeshes[1].bone_remap, [2, 3]);
+    let ids: Vec<i16> = submeshes[1]
+      .mesh
+      .vertices
+      .iter()
+      .map(|x| x.bone_weights[0].id)
+      .collect();
+    assert_eq!(ids, [0, 1, 1]);
+
+    assert_eq!(split_by_bones(&merged[0], 4).unwrap().len(), 1);
+    assert!(split_by_bones(&triangle(0_f32, [0, 1, 2]), 2).is_err());
+  }
+}
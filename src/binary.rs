@@ -1,11 +1,16 @@
-use super::ascii;
 use super::constants;
 use super::file_input::FileStream;
-use super::types::{Bone, BonePose, BoneWeight, Data, Header, Mesh, Texture, Vertex};
-use std::collections::HashMap;
+use super::material::RenderGroup;
+use super::mesh_buffers::MeshBuffers;
+use super::skinning;
+use super::texture_resolver;
+use super::types::{
+  Bone, BoneWeight, CameraTarget, Data, Header, HeaderFlags, HeaderSettings, Mesh, MeshItem,
+  RenderParameters, SettingsEntry, SettingsValue, Texture, Vertex,
+};
+use std::convert::TryFrom;
 use std::ffi::CString;
 use std::fmt::Write;
-use std::path::Path;
 use std::string::String;
 
 use super::error_types::XpsError;
@@ -46,10 +51,6 @@ fn read_xyz(file: &mut FileStream) -> [f32; 3] {
   [file.read_f32(), file.read_f32(), file.read_f32()]
 }
 
-fn has_tangent_header(header: &Header) -> bool {
-  header.version_mayor <= 1 && header.version_minor <= 12
-}
-
 fn read_header(file: &mut FileStream) -> Header {
   let mut header = Header::default();
 
@@ -61,13 +62,14 @@ fn read_header(file: &mut FileStream) -> Header {
   let machine_name = read_files_string(file);
   let username = read_files_string(file);
   let files_string = read_files_string(file);
-  let xps_pose_data = String::from("");
+  let mut xps_pose_data = String::from("");
+  let mut settings = HeaderSettings::default();
 
   if version_mayor <= 1 && version_minor <= 12 {
-    let _ = file.read((settings_length * 4) as usize);
+    settings.legacy = file.read((settings_length * 4) as usize);
   } else {
     let mut values_read = 0;
-    let _ = file.read_u32();
+    settings.hash = file.read_u32();
     values_read += 1 * 4;
     let items = file.read_u32();
     values_read += 1 * 4;
@@ -80,23 +82,29 @@ fn read_header(file: &mut FileStream) -> Header {
       let opt_info = file.read_u32();
       values_read += 1 * 4;
 
-      if option_type == 255 {
-        read_none(file, opt_count as usize);
+      let value = if option_type == constants::OPTION_NONE {
         values_read += opt_count * 2;
-      } else if option_type == 2 {
-        read_flags(file, opt_count as usize);
+        SettingsValue::Raw(read_none(file, opt_count as usize))
+      } else if option_type == constants::OPTION_FLAGS {
         values_read += opt_count * 2 * 4;
-      } else if option_type == 1 {
-        let _ = read_default_pose(file, opt_count, opt_info as usize);
+        SettingsValue::Flags(read_flags(file, opt_count as usize))
+      } else if option_type == constants::OPTION_POSE {
+        let pose_bytes = read_default_pose(file, opt_count);
+        xps_pose_data = decode_bytes(&pose_bytes[..opt_count as usize].to_vec());
         let read_count = round_to_multiple(opt_count as usize, constants::ROUND_MULTIPLE) as u32;
         values_read += read_count;
+        SettingsValue::Pose(pose_bytes)
       } else {
         let loop_start = values_read / 4;
         let loop_finish = settings_length;
-        for _ in loop_start..loop_finish {
-          let _ = file.read_u32();
-        }
-      }
+        SettingsValue::Raw((loop_start..loop_finish).map(|_| file.read_u32()).collect())
+      };
+      settings.entries.push(SettingsEntry {
+        option_type,
+        opt_count,
+        opt_info,
+        value,
+      });
     }
   }
 
@@ -108,6 +116,8 @@ fn read_header(file: &mut FileStream) -> Header {
   header.machine = machine_name;
   header.user = username;
   header.file = files_string;
+  header.flags = HeaderFlags::from_settings(&settings);
+  header.settings = settings;
   header.pose = xps_pose_data;
   header
 }
@@ -124,16 +134,14 @@ fn find_header(file: &mut FileStream) -> Result<Header, XpsError> {
   Err(XpsError::InvalidHeader)
 }
 
-fn read_none(file: &mut FileStream, opt_count: usize) {
-  for _ in 0..opt_count {
-    let _ = file.read_u32();
-  }
+fn read_none(file: &mut FileStream, opt_count: usize) -> Vec<u32> {
+  (0..opt_count).map(|_| file.read_u32()).collect()
 }
 
-fn read_flags(file: &mut FileStream, optcount: usize) {
-  for _ in 0..(optcount * 2) {
-    let _ = file.read_u32();
-  }
+fn read_flags(file: &mut FileStream, optcount: usize) -> Vec<(u32, u32)> {
+  (0..optcount)
+    .map(|_| (file.read_u32(), file.read_u32()))
+    .collect()
 }
 
 fn read_bones(file: &mut FileStream) -> Vec<Bone> {
@@ -149,6 +157,7 @@ fn read_bones(file: &mut FileStream) -> Vec<Bone> {
       name: CString::new(bone_name).unwrap_or(CString::new("").unwrap()),
       co: coords,
       parent_id: parent_id,
+      rest_rotation: super::math::QUAT_IDENTITY,
     };
     bones.push(bone)
   }
@@ -166,40 +175,39 @@ fn read_meshes(
   let has_header = true;
   let mut has_tangent = false;
   if has_header {
-    has_tangent = has_tangent_header(header)
+    has_tangent = header.has_tangents()
   }
+  let has_variable_weights = has_header && header.has_variable_weights();
   for _ in 0..mesh_count {
     let mut mesh_name = read_files_string(file);
     if mesh_name.len() == 0 {
       mesh_name = "unnamed".to_string();
     }
+    let parser = super::mesh_name_parser::MeshNameParser::new(&mesh_name);
+    let render_group = RenderGroup::new(parser.get_render_group_number());
     let uv_layer_count = file.read_u32() as usize;
     let mut textures = vec![];
     let tex_count = file.read_u32();
     for tex_id in 0..tex_count {
-      let texture_file = {
-        let filename = read_files_string(file);
-        match Path::new(&filename).parent() {
-          Some(x) => {
-            if let Some(y) = x.to_str() {
-              filename.replace(y, "").replace("\\", "/").replace("/", "")
-            } else {
-              return Err(XpsError::PathToStr);
-            }
-          }
-          None => return Err(XpsError::PathGetParent),
-        }
-      };
+      let texture_path = read_files_string(file);
+      let texture_file = texture_resolver::file_name(&texture_path).to_string();
       let uv_layer_id = file.read_u32();
 
       textures.push(Texture {
         id: tex_id as u16,
         file: CString::new(texture_file).unwrap_or(CString::new("").unwrap()),
+        path: CString::new(texture_path).unwrap_or(CString::new("").unwrap()),
         uv_layer: uv_layer_id as u16,
+        slot: render_group.texture_slot(tex_id as usize),
       });
     }
 
     let mut vertex = vec![];
+    let mut buffers = if params.mesh_buffers {
+      Some(MeshBuffers::new(uv_layer_count))
+    } else {
+      None
+    };
     let vertex_count = file.read_u32();
 
     for _ in 0..vertex_count {
@@ -212,16 +220,16 @@ fn read_meshes(
         file.read_byte(),
       ];
 
-      let mut uvs = [[0_f32; 2]; 3];
-      for uvx in 0..uv_layer_count {
-        uvs[uvx] = [file.read_f32(), {
+      let mut uvs = vec![];
+      for _ in 0..uv_layer_count {
+        uvs.push([file.read_f32(), {
           let v = file.read_f32();
           if params.flip_uv {
             1_f32 - v
           } else {
             v
           }
-        }];
+        }]);
 
         if !has_header || has_tangent {
           let _ = [
@@ -233,35 +241,32 @@ fn read_meshes(
         }
       }
 
-      let mut bone_weights = [BoneWeight::default(); 4];
+      let mut bone_weights = vec![BoneWeight::default(); skinning::MAX_INFLUENCES];
       if has_bones {
-        let idx = [
-          file.read_i16(),
-          file.read_i16(),
-          file.read_i16(),
-          file.read_i16(),
-        ];
-        let weight = [
-          file.read_f32(),
-          file.read_f32(),
-          file.read_f32(),
-          file.read_f32(),
-        ];
-        for x in 0..idx.len() {
-          bone_weights[x] = BoneWeight {
-            id: idx[x],
-            weight: weight[x],
-          };
-        }
+        let weight_count = if has_variable_weights {
+          usize::try_from(file.read_i16()).map_err(|_| XpsError::MeshReadBin)?
+        } else {
+          skinning::MAX_INFLUENCES
+        };
+        let idx: Vec<i16> = (0..weight_count).map(|_| file.read_i16()).collect();
+        let weight: Vec<f32> = (0..weight_count).map(|_| file.read_f32()).collect();
+        bone_weights = idx
+          .into_iter()
+          .zip(weight)
+          .map(|(id, weight)| BoneWeight { id, weight })
+          .collect();
+      }
+      match buffers.as_mut() {
+        Some(x) => x.push_vertex(coordinate, normal, vertex_color, &uvs, &bone_weights),
+        None => vertex.push(Vertex {
+          position: coordinate,
+          normal: normal,
+          color: vertex_color,
+          uv: uvs,
+          bone_weights: bone_weights,
+          merged: false,
+        }),
       }
-      vertex.push(Vertex {
-        position: coordinate,
-        normal: normal,
-        color: vertex_color,
-        uv: uvs,
-        bone_weights: bone_weights,
-        merged: false,
-      });
     }
 
     let mut faces = vec![];
@@ -277,17 +282,18 @@ fn read_meshes(
         faces.push(idx.2);
       }
     }
-    let parser = super::mesh_name_parser::MeshNameParser::new(&mesh_name);
-
     meshes.push(Mesh {
       name: CString::new(parser.mesh_name.clone()).unwrap_or(CString::new("").unwrap()),
+      full_name: CString::new(parser.full_name.clone()).unwrap_or_default(),
       textures: textures,
       vertices: vertex,
       faces: faces,
       uv_count: uv_layer_count as u16,
-      render_group: {
-        super::material::RenderGroup::new(parser.get_render_group_number())
-      },
+      render_group,
+      render_parameters: RenderParameters::from_parser(&parser),
+      item: MeshItem::from_parser(&parser),
+      camera: CameraTarget::from_parser(&parser),
+      buffers,
     });
   }
   Ok(meshes)
@@ -314,6 +320,10 @@ pub fn read_xps_model(
           header: header,
           bones: bones,
           meshes: meshes,
+          optional_items: vec![],
+          bone_order: vec![],
+          parameters: params,
+          warnings: vec![],
           error: XpsError::None,
         });
       }
@@ -326,22 +336,220 @@ pub fn read_xps_model(
   }
 }
 
-fn read_default_pose(
-  file: &mut FileStream,
-  pose_length_unround: u32,
-  pose_bones: usize,
-) -> HashMap<String, BonePose> {
-  let mut pose_bytes = vec![];
-  if pose_length_unround > 0 {
-    for _ in 0..pose_bones {
-      for byte in file.read_line().as_bytes() {
-        pose_bytes.push(*byte);
-      }
+// Pose text followed by its padding.
+fn read_default_pose(file: &mut FileStream, pose_length_unround: u32) -> Vec<u8> {
+  let pose_length = round_to_multiple(pose_length_unround as usize, constants::ROUND_MULTIPLE);
+  let mut pose_bytes = file.read(pose_length);
+  pose_bytes.resize(pose_length, 0);
+  pose_bytes
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::header_writer;
+
+  fn words(out: &mut Vec<u8>, values: &[u32]) {
+    for x in values {
+      out.extend_from_slice(&x.to_ne_bytes());
     }
   }
-  let pose_length = round_to_multiple(pose_length_unround as usize, constants::ROUND_MULTIPLE);
-  let empty = pose_length - pose_length_unround as usize;
-  file.read(empty);
-  let pose_string = decode_bytes(&pose_bytes);
-  ascii::pose_data(&pose_string)
+
+  fn header_bytes(version: (u16, u16), settings: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    words(&mut out, &[constants::MAGIC_NUMBER as u32]);
+    out.extend_from_slice(&version.0.to_ne_bytes());
+    out.extend_from_slice(&version.1.to_ne_bytes());
+    header_writer::write_files_string(&mut out, constants::XNA_ARAL);
+    words(&mut out, &[(settings.len() / 4) as u32]);
+    header_writer::write_files_string(&mut out, "machine");
+    header_writer::write_files_string(&mut out, "user");
+    header_writer::write_files_string(&mut out, "files");
+    out.extend_from_slice(settings);
+    out
+  }
+
+  fn flags_settings(pairs: &[(u32, u32)]) -> Vec<u8> {
+    let mut settings = vec![];
+    words(
+      &mut settings,
+      &[0, 1, constants::OPTION_FLAGS, pairs.len() as u32, 0],
+    );
+    for (key, value) in pairs {
+      words(&mut settings, &[*key, *value]);
+    }
+    settings
+  }
+
+  // Header, an optional root bone and one mesh with `uv_layers` layers and
+  // no triangles; `vertex` is appended after the vertex count.
+  fn model_bytes(version: (u16, u16), uv_layers: u32, has_bones: bool, vertex: &[u8]) -> Vec<u8> {
+    let mut out = header_bytes(version, &flags_settings(&[]));
+    if has_bones {
+      words(&mut out, &[1]);
+      header_writer::write_files_string(&mut out, "root");
+      out.extend_from_slice(&(-1_i16).to_ne_bytes());
+      floats(&mut out, &[0_f32; 3]);
+    } else {
+      words(&mut out, &[0]);
+    }
+    words(&mut out, &[1]);
+    header_writer::write_files_string(&mut out, "1_mesh");
+    words(&mut out, &[uv_layers, 0, 1]);
+    out.extend_from_slice(vertex);
+    words(&mut out, &[0]);
+    out
+  }
+
+  fn floats(out: &mut Vec<u8>, values: &[f32]) {
+    for x in values {
+      out.extend_from_slice(&x.to_ne_bytes());
+    }
+  }
+
+  fn load(bytes: &[u8], name: &str) -> Result<Data, XpsError> {
+    let filename = std::env::temp_dir().join(name);
+    std::fs::write(&filename, bytes).unwrap();
+    let data = read_xps_model(
+      &filename.to_str().unwrap().to_string(),
+      crate::types::ImportParameters {
+        flip_uv: false,
+        ..Default::default()
+      },
+    );
+    let _ = std::fs::remove_file(&filename);
+    data
+  }
+
+  fn round_trip(bytes: &[u8], name: &str) -> Header {
+    let filename = std::env::temp_dir().join(name);
+    std::fs::write(&filename, bytes).unwrap();
+    let mut file = FileStream::new(&filename.to_str().unwrap().to_string(), false).unwrap();
+    let header = read_header(&mut file);
+    let _ = std::fs::remove_file(&filename);
+    assert_eq!(header_writer::write_header(&header), bytes);
+    header
+  }
+
+  #[test]
+  fn header_round_trips_byte_for_byte() {
+    let pose = b"root ground: 0 0 0 0 0 0 1 1 1\n";
+    let mut settings = vec![];
+    words(&mut settings, &[7, 3]);
+    words(&mut settings, &[constants::OPTION_NONE, 2, 0, 11, 12]);
+    words(&mut settings, &[constants::OPTION_FLAGS, 2, 0]);
+    words(&mut settings, &[constants::FLAG_BACKFACE_CULLING, 1, 42, 5]);
+    words(
+      &mut settings,
+      &[constants::OPTION_POSE, pose.len() as u32, 1],
+    );
+    settings.extend_from_slice(pose);
+    settings.resize(
+      round_to_multiple(settings.len(), constants::ROUND_MULTIPLE),
+      0,
+    );
+
+    let header = round_trip(
+      &header_bytes((2, 15), &settings),
+      "xpsimport_header_test.xps",
+    );
+    assert_eq!(header.settings.entries.len(), 3);
+    assert!(header.flags.backface_culling);
+    assert_eq!(header.flags.unknown, vec![(42, 5)]);
+    assert_eq!(header.pose, String::from_utf8_lossy(pose));
+  }
+
+  #[test]
+  fn legacy_header_round_trips_byte_for_byte() {
+    let settings: Vec<u8> = (0..40).collect();
+    let header = round_trip(
+      &header_bytes((1, 12), &settings),
+      "xpsimport_legacy_header_test.xps",
+    );
+    assert_eq!(header.settings.legacy, settings);
+  }
+
+  #[test]
+  fn tangents_and_weight_counts_follow_the_version() {
+    let bytes = model_bytes((2, 15), 1, false, &vertex_start());
+    let data = load(&bytes, "xpsimport_tangent_version_test.xps").unwrap();
+    assert!(!data.header.has_tangents());
+    assert!(!data.header.has_variable_weights());
+    assert_eq!(data.meshes[0].vertices[0].uv, vec![[0.25, 0.5]]);
+    assert!(data.meshes[0].faces.is_empty());
+
+    let header = round_trip(
+      &header_bytes((1, 12), &[0; 8]),
+      "xpsimport_tangent_default_test.xps",
+    );
+    assert!(header.has_tangents());
+    let header = round_trip(
+      &header_bytes((3, 0), &flags_settings(&[])),
+      "xpsimport_weight_version_test.xps",
+    );
+    assert!(header.has_variable_weights());
+  }
+
+  // Position, normal, color and UV of a vertex without tangents.
+  fn vertex_start() -> Vec<u8> {
+    let mut vertex = vec![];
+    floats(&mut vertex, &[1_f32, 2_f32, 3_f32, 0_f32, 0_f32, 1_f32]);
+    vertex.extend_from_slice(&[255; 4]);
+    floats(&mut vertex, &[0.25, 0.5]);
+    vertex
+  }
+
+  #[test]
+  fn variable_weight_counts_are_read_per_vertex() {
+    let mut vertex = vertex_start();
+    vertex.extend_from_slice(&2_i16.to_ne_bytes());
+    for id in &[0_i16, 7_i16] {
+      vertex.extend_from_slice(&id.to_ne_bytes());
+    }
+    floats(&mut vertex, &[0.75, 0.25]);
+    let bytes = model_bytes((3, 0), 1, true, &vertex);
+    let data = load(&bytes, "xpsimport_variable_weights_test.xps").unwrap();
+    let weights = &data.meshes[0].vertices[0].bone_weights;
+    assert_eq!(
+      weights.iter().map(|x| x.id).collect::<Vec<i16>>(),
+      vec![0, 7]
+    );
+    assert_eq!(
+      weights.iter().map(|x| x.weight).collect::<Vec<f32>>(),
+      vec![0.75, 0.25]
+    );
+  }
+
+  #[test]
+  fn meshes_keep_more_than_three_uv_layers() {
+    let mut vertex = vertex_start();
+    floats(&mut vertex, &[0.5, 0.5, 0.75, 0.25, 1_f32, 0.125]);
+    let bytes = model_bytes((2, 15), 4, false, &vertex);
+    let data = load(&bytes, "xpsimport_uv_layers_test.xps").unwrap();
+    let mesh = &data.meshes[0];
+    assert_eq!(mesh.uv_count, 4);
+    assert_eq!(
+      mesh.vertices[0].uv,
+      vec![[0.25, 0.5], [0.5, 0.5], [0.75, 0.25], [1_f32, 0.125]]
+    );
+    assert_eq!(mesh.vertex_uv(0, 3), Some([1_f32, 0.125]));
+    assert_eq!(mesh.vertex_uv(0, 4), None);
+  }
+
+  #[test]
+  fn negative_weight_count_is_an_error() {
+    let mut vertex = vertex_start();
+    vertex.extend_from_slice(&(-1_i16).to_ne_bytes());
+    let bytes = model_bytes((3, 0), 1, true, &vertex);
+    assert!(load(&bytes, "xpsimport_negative_count_test.xps").is_err());
+  }
+
+  #[test]
+  fn meshes_without_bones_keep_four_weight_slots() {
+    let bytes = model_bytes((3, 0), 1, false, &vertex_start());
+    let data = load(&bytes, "xpsimport_no_bones_test.xps").unwrap();
+    let weights = &data.meshes[0].vertices[0].bone_weights;
+    assert_eq!(weights.len(), skinning::MAX_INFLUENCES);
+    assert!(weights.iter().all(|x| x.id == 0 && x.weight == 0_f32));
+  }
 }
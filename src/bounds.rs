@@ -0,0 +1,163 @@
+use super::math;
+use super::types::{Data, Mesh};
+
+#[derive(Clone, Copy)]
+pub struct Aabb {
+  pub min: [f32; 3],
+  pub max: [f32; 3],
+}
+
+#[derive(Clone, Copy)]
+pub struct Sphere {
+  pub center: [f32; 3],
+  pub radius: f32,
+}
+
+impl Aabb {
+  pub fn center(&self) -> [f32; 3] {
+    math::scale(math::add(self.min, self.max), 0.5)
+  }
+
+  pub fn size(&self) -> [f32; 3] {
+    math::sub(self.max, self.min)
+  }
+
+  pub fn extend(&mut self, point: [f32; 3]) {
+    for (axis, value) in point.iter().enumerate() {
+      self.min[axis] = self.min[axis].min(*value);
+      self.max[axis] = self.max[axis].max(*value);
+    }
+  }
+
+  fn from_points<'a, I: Iterator<Item = &'a [f32; 3]>>(points: I) -> Option<Aabb> {
+    let mut aabb: Option<Aabb> = None;
+    for point in points.filter(|x| x.iter().all(|v| v.is_finite())) {
+      match aabb.as_mut() {
+        Some(x) => x.extend(*point),
+        None => {
+          aabb = Some(Aabb {
+            min: *point,
+            max: *point,
+          })
+        }
+      }
+    }
+    aabb
+  }
+}
+
+fn sphere_around<'a, I: Iterator<Item = &'a [f32; 3]> + Clone>(points: I) -> Option<Sphere> {
+  let center = Aabb::from_points(points.clone())?.center();
+  let radius = points
+    .filter(|x| x.iter().all(|v| v.is_finite()))
+    .map(|x| math::length(math::sub(*x, center)))
+    .fold(0_f32, f32::max);
+  Some(Sphere { center, radius })
+}
+
+impl Mesh {
+  pub fn aabb(&self) -> Option<Aabb> {
+    Aabb::from_points(self.vertex_positions())
+  }
+
+  pub fn bounding_sphere(&self) -> Option<Sphere> {
+    sphere_around(self.vertex_positions())
+  }
+
+  fn influenced_by(&self, index: usize, bone_index: usize, threshold: f32) -> bool {
+    (0..self.vertex_bone_weight_count(index))
+      .map(|x| self.vertex_bone_weight(index, x))
+      .any(|x| x.id as usize == bone_index && x.weight > threshold)
+  }
+}
+
+impl Data {
+  pub fn aabb(&self) -> Option<Aabb> {
+    Aabb::from_points(self.meshes.iter().flat_map(|x| x.vertex_positions()))
+  }
+
+  pub fn bounding_sphere(&self) -> Option<Sphere> {
+    sphere_around(self.meshes.iter().flat_map(|x| x.vertex_positions()))
+  }
+
+  // Bounds of the vertices a bone influences with more than `threshold` weight.
+  pub fn bone_aabb(&self, bone_index: usize, threshold: f32) -> Option<Aabb> {
+    let points: Vec<[f32; 3]> = self
+      .meshes
+      .iter()
+      .flat_map(|mesh| {
+        (0..mesh.vertex_count())
+          .filter(move |x| mesh.influenced_by(*x, bone_index, threshold))
+          .map(move |x| mesh.vertex_position(x))
+      })
+      .collect();
+    Aabb::from_points(points.iter())
+  }
+
+  pub fn bone_bounds(&self, threshold: f32) -> Vec<Option<Aabb>> {
+    let mut bounds: Vec<Option<Aabb>> = vec![None; self.bones.len()];
+    for mesh in self.meshes.iter() {
+      for index in 0..mesh.vertex_count() {
+        let position = mesh.vertex_position(index);
+        if !position.iter().all(|x| x.is_finite()) {
+          continue;
+        }
+        for slot in 0..mesh.vertex_bone_weight_count(index) {
+          let bone_weight = mesh.vertex_bone_weight(index, slot);
+          if bone_weight.weight <= threshold || bone_weight.id < 0 {
+            continue;
+          }
+          if let Some(slot) = bounds.get_mut(bone_weight.id as usize) {
+            match slot.as_mut() {
+              Some(x) => x.extend(position),
+              None => {
+                *slot = Some(Aabb {
+                  min: position,
+                  max: position,
+                })
+              }
+            }
+          }
+        }
+      }
+    }
+    bounds
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  #[test]
+  fn cube_bounds() {
+    let mut mesh = test_util::cube(false);
+    for vertex in mesh.vertices.iter_mut() {
+      vertex.position = math::add(vertex.position, [1_f32, 2_f32, 3_f32]);
+      if vertex.position[0] > 1_f32 {
+        vertex.bone_weights[0].id = 1;
+      }
+    }
+    mesh.vertices[0].position = [f32::NAN; 3];
+
+    let aabb = mesh.aabb().unwrap();
+    assert_eq!(aabb.min, [0.5, 1.5, 2.5]);
+    assert_eq!(aabb.max, [1.5, 2.5, 3.5]);
+    assert_eq!(aabb.center(), [1_f32, 2_f32, 3_f32]);
+    assert_eq!(aabb.size(), [1_f32; 3]);
+    let sphere = mesh.bounding_sphere().unwrap();
+    assert_eq!(sphere.center, [1_f32, 2_f32, 3_f32]);
+    assert!((sphere.radius - 3_f32.sqrt() / 2_f32).abs() < 1e-6);
+
+    let data = Data {
+      meshes: vec![mesh],
+      ..Default::default()
+    };
+    assert_eq!(data.aabb().unwrap().max, aabb.max);
+    let bone = data.bone_aabb(1, 0_f32).unwrap();
+    assert_eq!(bone.min, [1.5, 1.5, 2.5]);
+    assert_eq!(bone.max, [1.5, 2.5, 3.5]);
+    assert!(data.bone_aabb(2, 0_f32).is_none());
+  }
+}
@@ -0,0 +1,114 @@
+use super::error_types::{WarningKind, XpsWarning};
+use super::types::{Bone, Data};
+
+// Default target written by the parser when a camera lists no bones.
+pub const ROOT_TARGET: &str = "root";
+
+fn find_bone(bones: &[Bone], target: &str) -> Option<usize> {
+  let name = |x: &Bone| x.name.to_string_lossy().to_string();
+  bones
+    .iter()
+    .position(|x| name(x) == target)
+    .or_else(|| {
+      bones
+        .iter()
+        .position(|x| name(x).eq_ignore_ascii_case(target))
+    })
+    .or_else(|| {
+      if target.eq_ignore_ascii_case(ROOT_TARGET) {
+        bones.iter().position(|x| x.parent_id < 0)
+      } else {
+        None
+      }
+    })
+}
+
+// Fills in the bone indices of every camera target. Must run while the bones
+// still have their names from the file.
+pub fn resolve_camera_targets(data: &mut Data) -> Vec<XpsWarning> {
+  let mut warnings = vec![];
+  for (mesh_index, mesh) in data.meshes.iter_mut().enumerate() {
+    let camera = match mesh.camera.as_mut() {
+      Some(x) => x,
+      None => continue,
+    };
+    for (index, target) in camera.targets.iter().enumerate() {
+      let target = target.to_string_lossy();
+      camera.bone_indices[index] = find_bone(&data.bones, &target);
+      if camera.bone_indices[index].is_none() {
+        warnings.push(XpsWarning::new(
+          WarningKind::UnresolvedCameraTarget,
+          Some(mesh_index),
+          format!("camera target '{}' does not match any bone", target),
+        ));
+      }
+    }
+  }
+  warnings
+}
+
+impl Data {
+  // Resolved target bones of all meshes, without duplicates.
+  pub fn camera_target_bones(&self) -> Vec<usize> {
+    let mut bones: Vec<usize> = vec![];
+    for camera in self.meshes.iter().filter_map(|x| x.camera.as_ref()) {
+      for x in camera.bone_indices.iter().flatten() {
+        if !bones.contains(x) {
+          bones.push(*x);
+        }
+      }
+    }
+    bones
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mesh_name_parser::MeshNameParser;
+  use crate::test_util;
+  use crate::types::CameraTarget;
+  use std::ffi::CString;
+
+  fn bone(id: i16, name: &str, parent_id: i16) -> Bone {
+    Bone {
+      id,
+      name: CString::new(name).unwrap(),
+      co: [0_f32; 3],
+      parent_id,
+      rest_rotation: crate::math::QUAT_IDENTITY,
+    }
+  }
+
+  fn camera_mesh(name: &str) -> crate::types::Mesh {
+    let mut mesh = test_util::mesh(vec![], vec![]);
+    mesh.camera = CameraTarget::from_parser(&MeshNameParser::new(&name.to_string()));
+    mesh
+  }
+
+  #[test]
+  fn targets_fall_back_to_root_and_report_unknown_bones() {
+    let mut data = Data {
+      bones: vec![bone(0, "root ground", -1), bone(1, "Head", 0)],
+      meshes: vec![
+        camera_mesh("22_body_0.3_4_8_cam"),
+        camera_mesh("22_hat_0.3_4_8_cam_head_tail"),
+      ],
+      ..Default::default()
+    };
+    let warnings = resolve_camera_targets(&mut data);
+
+    assert_eq!(
+      data.meshes[0].camera.as_ref().unwrap().bone_indices,
+      [Some(0)]
+    );
+    assert_eq!(
+      data.meshes[1].camera.as_ref().unwrap().bone_indices,
+      [Some(1), None]
+    );
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].kind, WarningKind::UnresolvedCameraTarget);
+    assert_eq!(warnings[0].mesh_index, Some(1));
+    assert_eq!(data.camera_target_bones(), [0, 1]);
+  }
+}
@@ -7,6 +7,21 @@ pub const LIMIT: usize = 128;
 
 pub const ROUND_MULTIPLE: usize = 4;
 
+// Option types of the binary settings block.
+pub const OPTION_NONE: u32 = 255;
+pub const OPTION_POSE: u32 = 1;
+pub const OPTION_FLAGS: u32 = 2;
+
+// Keys of the flags option.
+pub const FLAG_BACKFACE_CULLING: u32 = 0;
+pub const FLAG_ALWAYS_FORCE_CULLING: u32 = 1;
+pub const FLAG_MODEL_CAST_SHADOWS: u32 = 2;
+pub const FLAG_TANGENT_SPACE_RED: u32 = 3;
+pub const FLAG_TANGENT_SPACE_GREEN: u32 = 4;
+pub const FLAG_TANGENT_SPACE_BLUE: u32 = 5;
+pub const FLAG_GLOSS: u32 = 6;
+pub const FLAG_HAS_BONE_DIRECTIONS: u32 = 7;
+
 pub const ENCODING_READ: &str = "utf-8-sig";
 pub const ENCODING_WRITE: &str = "utf-8";
 
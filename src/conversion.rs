@@ -0,0 +1,225 @@
+use super::math;
+use super::math::{Quat, Vec3};
+use super::types::{BonePose, Data, ImportParameters};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Axis {
+  PositiveX,
+  NegativeX,
+  PositiveY,
+  NegativeY,
+  PositiveZ,
+  NegativeZ,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Handedness {
+  Right,
+  Left,
+}
+
+// XPS models are Y-up, face +Z and are right-handed.
+pub const XPS_UP: Axis = Axis::PositiveY;
+pub const XPS_FORWARD: Axis = Axis::PositiveZ;
+pub const XPS_HANDEDNESS: Handedness = Handedness::Right;
+
+impl Axis {
+  pub fn vector(self) -> Vec3 {
+    match self {
+      Axis::PositiveX => [1_f32, 0_f32, 0_f32],
+      Axis::NegativeX => [-1_f32, 0_f32, 0_f32],
+      Axis::PositiveY => [0_f32, 1_f32, 0_f32],
+      Axis::NegativeY => [0_f32, -1_f32, 0_f32],
+      Axis::PositiveZ => [0_f32, 0_f32, 1_f32],
+      Axis::NegativeZ => [0_f32, 0_f32, -1_f32],
+    }
+  }
+
+  pub fn from_index(index: i32) -> Option<Axis> {
+    match index {
+      0 => Some(Axis::PositiveX),
+      1 => Some(Axis::NegativeX),
+      2 => Some(Axis::PositiveY),
+      3 => Some(Axis::NegativeY),
+      4 => Some(Axis::PositiveZ),
+      5 => Some(Axis::NegativeZ),
+      _ => None,
+    }
+  }
+}
+
+// Maps XPS coordinates to the target space; columns are where XPS +X, +Y
+// and +Z end up.
+pub struct AxisConversion {
+  columns: [Vec3; 3],
+  scale: f32,
+}
+
+fn side(up: Vec3, forward: Vec3, handedness: Handedness) -> Vec3 {
+  let x = math::cross(up, forward);
+  match handedness {
+    Handedness::Right => x,
+    Handedness::Left => math::scale(x, -1_f32),
+  }
+}
+
+impl AxisConversion {
+  // None when the axes are not perpendicular or the scale is zero or not
+  // finite.
+  pub fn new(
+    up: Axis,
+    forward: Axis,
+    handedness: Handedness,
+    scale: f32,
+  ) -> Option<AxisConversion> {
+    let (up, forward) = (up.vector(), forward.vector());
+    if math::dot(up, forward) != 0_f32 || !scale.is_finite() || scale == 0_f32 {
+      return None;
+    }
+    Some(AxisConversion {
+      columns: [side(up, forward, handedness), up, forward],
+      scale,
+    })
+  }
+
+  pub fn from_parameters(parameters: &ImportParameters) -> Option<AxisConversion> {
+    AxisConversion::new(
+      parameters.up_axis,
+      parameters.forward_axis,
+      parameters.handedness,
+      parameters.scale,
+    )
+  }
+
+  pub fn is_identity(&self) -> bool {
+    self.scale == 1_f32
+      && self.columns
+        == [
+          [1_f32, 0_f32, 0_f32],
+          [0_f32, 1_f32, 0_f32],
+          [0_f32, 0_f32, 1_f32],
+        ]
+  }
+
+  // A negative scale mirrors through the origin, which flips handedness on
+  // its own.
+  pub fn flips_handedness(&self) -> bool {
+    let mirrored = math::dot(
+      math::cross(self.columns[0], self.columns[1]),
+      self.columns[2],
+    ) < 0_f32;
+    mirrored != (self.scale < 0_f32)
+  }
+
+  pub fn direction(&self, v: Vec3) -> Vec3 {
+    math::add(
+      math::add(
+        math::scale(self.columns[0], v[0]),
+        math::scale(self.columns[1], v[1]),
+      ),
+      math::scale(self.columns[2], v[2]),
+    )
+  }
+
+  pub fn point(&self, v: Vec3) -> Vec3 {
+    math::scale(self.direction(v), self.scale)
+  }
+
+  // Normals follow the sign of the scale so they keep pointing outwards.
+  pub fn normal(&self, v: Vec3) -> Vec3 {
+    math::scale(self.direction(v), self.scale.signum())
+  }
+
+  fn row(&self, index: usize) -> Vec3 {
+    [
+      self.columns[0][index],
+      self.columns[1][index],
+      self.columns[2][index],
+    ]
+  }
+
+  // M * R * M^T stays a proper rotation even when M mirrors.
+  pub fn rotation(&self, q: Quat) -> Quat {
+    let x = self.direction(math::quat_rotate(q, self.row(0)));
+    let y = self.direction(math::quat_rotate(q, self.row(1)));
+    let z = self.direction(math::quat_rotate(q, self.row(2)));
+    math::quat_from_basis(x, y, z)
+  }
+}
+
+pub fn convert_model(data: &mut Data, conversion: &AxisConversion) {
+  if conversion.is_identity() {
+    return;
+  }
+  for bone in data.bones.iter_mut() {
+    bone.co = conversion.point(bone.co);
+    bone.rest_rotation = conversion.rotation(bone.rest_rotation);
+  }
+  for mesh in data.meshes.iter_mut() {
+    for vertex in mesh.vertices.iter_mut() {
+      vertex.position = conversion.point(vertex.position);
+      vertex.normal = conversion.normal(vertex.normal);
+    }
+    if let Some(buffers) = mesh.buffers.as_mut() {
+      for position in buffers.positions.iter_mut() {
+        *position = conversion.point(*position);
+      }
+      for normal in buffers.normals.iter_mut() {
+        *normal = conversion.normal(*normal);
+      }
+    }
+    if conversion.flips_handedness() {
+      for face in mesh.faces.chunks_exact_mut(3) {
+        face.swap(1, 2);
+      }
+    }
+  }
+}
+
+pub fn convert_pose(pose: &mut HashMap<String, BonePose>, conversion: &AxisConversion) {
+  if conversion.is_identity() {
+    return;
+  }
+  for bone_pose in pose.values_mut() {
+    bone_pose.coordinate_delta = conversion.point(bone_pose.coordinate_delta);
+    let scale = conversion.direction(bone_pose.scale);
+    bone_pose.scale = [scale[0].abs(), scale[1].abs(), scale[2].abs()];
+    bone_pose.rotation_delta = math::quat_to_xps_euler(
+      conversion.rotation(math::quat_from_xps_euler(bone_pose.rotation_delta)),
+    );
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  #[test]
+  fn negative_scale_flips_winding_and_keeps_normals_outwards() {
+    let conversion = AxisConversion::new(XPS_UP, XPS_FORWARD, XPS_HANDEDNESS, -2_f32).unwrap();
+    assert!(conversion.flips_handedness());
+    let mirrored = AxisConversion::new(XPS_UP, XPS_FORWARD, Handedness::Left, -2_f32).unwrap();
+    assert!(!mirrored.flips_handedness());
+
+    let mut data = Data {
+      meshes: vec![test_util::cube(true)],
+      ..Default::default()
+    };
+    convert_model(&mut data, &conversion);
+    let mesh = &data.meshes[0];
+    assert_eq!(mesh.faces[..3], [0, 2, 1]);
+    for vertex in &mesh.vertices {
+      assert!(math::dot(vertex.normal, vertex.position) > 0_f32);
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_parameters() {
+    assert!(AxisConversion::new(Axis::PositiveY, Axis::NegativeY, XPS_HANDEDNESS, 1_f32).is_none());
+    for scale in [0_f32, f32::NAN, f32::INFINITY].iter() {
+      assert!(AxisConversion::new(XPS_UP, XPS_FORWARD, XPS_HANDEDNESS, *scale).is_none());
+    }
+  }
+}
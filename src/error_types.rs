@@ -1,5 +1,6 @@
 use core::fmt;
 use core::fmt::Debug;
+use std::ffi::CString;
 
 
 #[derive(Clone, Copy)]
@@ -13,6 +14,10 @@ pub enum XpsError {
   MeshReadAscii,
   MeshReadBin,
   None,
+  FileNotWritten,
+  TextureNotDecoded,
+  BonePaletteExceeded,
+  InvalidArgument,
 }
 
 impl Default for XpsError {
@@ -33,6 +38,37 @@ impl Debug for XpsError {
       XpsError::MeshReadBin => write!(f, "MeshReadBin"),
       XpsError::Unknown => write!(f, "Unknown"),
       XpsError::None => write!(f, "None"),
+      XpsError::FileNotWritten => write!(f, "FileNotWritten"),
+      XpsError::TextureNotDecoded => write!(f, "TextureNotDecoded"),
+      XpsError::BonePaletteExceeded => write!(f, "BonePaletteExceeded"),
+      XpsError::InvalidArgument => write!(f, "InvalidArgument"),
+    }
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WarningKind {
+  TextureCountMismatch,
+  UnknownRenderGroup,
+  InvalidMeshName,
+  UnresolvedCameraTarget,
+  InvalidAxisConversion,
+}
+
+// Problems found while loading that did not stop the model from loading.
+#[derive(Clone, Debug)]
+pub struct XpsWarning {
+  pub kind: WarningKind,
+  pub mesh_index: Option<usize>,
+  pub message: CString,
+}
+
+impl XpsWarning {
+  pub fn new(kind: WarningKind, mesh_index: Option<usize>, message: String) -> XpsWarning {
+    XpsWarning {
+      kind,
+      mesh_index,
+      message: CString::new(message).unwrap_or_default(),
     }
   }
 }
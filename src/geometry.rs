@@ -0,0 +1,174 @@
+use super::math;
+use super::types::{Data, ImportParameters, Mesh};
+use super::weld;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NormalWeighting {
+  Area,
+  Angle,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NormalMode {
+  Recompute,
+  RepairInvalid,
+}
+
+#[derive(Clone, Copy)]
+pub struct NormalOptions {
+  pub weighting: NormalWeighting,
+  pub mode: NormalMode,
+  pub weld_seams: bool,
+  pub weld_tolerance: f32,
+  pub fix_inverted: bool,
+  pub reverse_winding: bool,
+}
+
+impl NormalOptions {
+  // XPS triangles are stored clockwise, so face normals computed from the
+  // file order point inwards unless the winding was reversed on import.
+  pub fn new(parameters: &ImportParameters) -> NormalOptions {
+    NormalOptions {
+      weighting: NormalWeighting::Angle,
+      mode: NormalMode::Recompute,
+      weld_seams: false,
+      weld_tolerance: 1e-5,
+      fix_inverted: false,
+      reverse_winding: parameters.reverse_winding,
+    }
+  }
+}
+
+fn is_valid(normal: [f32; 3]) -> bool {
+  normal.iter().all(|x| x.is_finite()) && math::length(normal) > 1e-6
+}
+
+fn corner_angle(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> f32 {
+  let u = math::normalize(math::sub(b, a));
+  let v = math::normalize(math::sub(c, a));
+  math::dot(u, v).clamp(-1_f32, 1_f32).acos()
+}
+
+pub fn face_normal(mesh: &Mesh, face: &[u32], reverse_winding: bool) -> [f32; 3] {
+  let a = mesh.vertex_position(face[0] as usize);
+  let b = mesh.vertex_position(face[1] as usize);
+  let c = mesh.vertex_position(face[2] as usize);
+  let n = math::cross(math::sub(b, a), math::sub(c, a));
+  if reverse_winding {
+    n
+  } else {
+    math::scale(n, -1_f32)
+  }
+}
+
+fn accumulate(mesh: &Mesh, options: &NormalOptions) -> Vec<[f32; 3]> {
+  let mut sums = vec![[0_f32; 3]; mesh.vertex_count()];
+  for face in mesh.faces.chunks_exact(3) {
+    if face.iter().any(|x| *x as usize >= mesh.vertex_count()) {
+      continue;
+    }
+    let n = face_normal(mesh, face, options.reverse_winding);
+    if !is_valid(n) {
+      continue;
+    }
+    for corner in 0..3 {
+      let weight = match options.weighting {
+        NormalWeighting::Area => 1_f32,
+        NormalWeighting::Angle => corner_angle(
+          mesh.vertex_position(face[corner] as usize),
+          mesh.vertex_position(face[(corner + 1) % 3] as usize),
+          mesh.vertex_position(face[(corner + 2) % 3] as usize),
+        ),
+      };
+      let direction = match options.weighting {
+        NormalWeighting::Area => n,
+        NormalWeighting::Angle => math::normalize(n),
+      };
+      let sum = &mut sums[face[corner] as usize];
+      *sum = math::add(*sum, math::scale(direction, weight));
+    }
+  }
+  if options.weld_seams {
+    for group in weld::seam_groups(mesh, options.weld_tolerance) {
+      let total = group
+        .iter()
+        .fold([0_f32; 3], |acc, x| math::add(acc, sums[*x as usize]));
+      for x in group {
+        sums[x as usize] = total;
+      }
+    }
+  }
+  sums
+}
+
+pub fn recompute_normals(mesh: &mut Mesh, options: &NormalOptions) {
+  let sums = accumulate(mesh, options);
+  for (normal, sum) in mesh.vertex_normals_mut().into_iter().zip(sums) {
+    if !is_valid(sum) {
+      continue;
+    }
+    let computed = math::normalize(sum);
+    let replace = match options.mode {
+      NormalMode::Recompute => true,
+      NormalMode::RepairInvalid => !is_valid(*normal),
+    };
+    if replace {
+      *normal = computed;
+    } else if options.fix_inverted && math::dot(*normal, computed) < 0_f32 {
+      *normal = math::scale(*normal, -1_f32);
+    }
+  }
+}
+
+pub fn recompute_model_normals(data: &mut Data, options: &NormalOptions) {
+  for mesh in data.meshes.iter_mut() {
+    recompute_normals(mesh, options);
+  }
+}
+
+pub fn flip_normals(mesh: &mut Mesh) {
+  for normal in mesh.vertex_normals_mut() {
+    *normal = math::scale(*normal, -1_f32);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  fn options(weld_seams: bool) -> NormalOptions {
+    NormalOptions {
+      weighting: NormalWeighting::Angle,
+      mode: NormalMode::Recompute,
+      weld_seams,
+      weld_tolerance: 1e-5,
+      fix_inverted: false,
+      reverse_winding: true,
+    }
+  }
+
+  fn close(a: [f32; 3], b: [f32; 3]) -> bool {
+    math::length(math::sub(a, b)) < 1e-5
+  }
+
+  #[test]
+  fn seams_are_smoothed_only_when_welded() {
+    let flat = test_util::cube(false);
+
+    let mut mesh = flat.clone();
+    recompute_normals(&mut mesh, &options(false));
+    for (vertex, original) in mesh.vertices.iter().zip(&flat.vertices) {
+      assert!(close(vertex.normal, original.normal));
+    }
+
+    let mut mesh = flat.clone();
+    for vertex in mesh.vertices.iter_mut() {
+      vertex.normal = [f32::NAN; 3];
+    }
+    recompute_normals(&mut mesh, &options(true));
+    for vertex in &mesh.vertices {
+      assert!(close(vertex.normal, math::normalize(vertex.position)));
+    }
+  }
+}
@@ -0,0 +1,65 @@
+use super::constants;
+use super::types::{Header, HeaderSettings, SettingsValue};
+
+// Inverse of `binary::read_files_string`; characters are stored as single
+// bytes.
+pub fn write_files_string(out: &mut Vec<u8>, string: &str) {
+  let bytes: Vec<u8> = string.chars().map(|x| x as u8).collect();
+  let length = bytes.len();
+  if length >= constants::LIMIT {
+    out.push((length % constants::LIMIT + constants::LIMIT) as u8);
+    out.push((length / constants::LIMIT) as u8);
+  } else {
+    out.push(length as u8);
+  }
+  out.extend_from_slice(&bytes);
+}
+
+fn write_u32(out: &mut Vec<u8>, value: u32) {
+  out.extend_from_slice(&value.to_ne_bytes());
+}
+
+pub fn write_settings(out: &mut Vec<u8>, header: &Header) {
+  let settings: &HeaderSettings = &header.settings;
+  if header.version_mayor <= 1 && header.version_minor <= 12 {
+    out.extend_from_slice(&settings.legacy);
+    return;
+  }
+  write_u32(out, settings.hash);
+  write_u32(out, settings.entries.len() as u32);
+  for entry in settings.entries.iter() {
+    write_u32(out, entry.option_type);
+    write_u32(out, entry.opt_count);
+    write_u32(out, entry.opt_info);
+    match &entry.value {
+      SettingsValue::Flags(x) => {
+        for (key, value) in x.iter() {
+          write_u32(out, *key);
+          write_u32(out, *value);
+        }
+      }
+      SettingsValue::Pose(x) => out.extend_from_slice(x),
+      SettingsValue::Raw(x) => {
+        for value in x.iter() {
+          write_u32(out, *value);
+        }
+      }
+    }
+  }
+}
+
+// Header bytes as stored in a binary .xps/.mesh file. A header read from a
+// file is written back unchanged.
+pub fn write_header(header: &Header) -> Vec<u8> {
+  let mut out = vec![];
+  write_u32(&mut out, header.magic_number);
+  out.extend_from_slice(&header.version_mayor.to_ne_bytes());
+  out.extend_from_slice(&header.version_minor.to_ne_bytes());
+  write_files_string(&mut out, &header.aral);
+  write_u32(&mut out, header.settings_length);
+  write_files_string(&mut out, &header.machine);
+  write_files_string(&mut out, &header.user);
+  write_files_string(&mut out, &header.file);
+  write_settings(&mut out, header);
+  out
+}
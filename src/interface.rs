@@ -1,9 +1,13 @@
+use super::conversion::{Axis, Handedness};
 use super::error_types::XpsError;
-use super::loader::open;
+use super::loader::{open, open_with_parameters};
+use super::material::{RenderGroupId, TextureSlot};
+use super::mesh_buffers::{IndexBuffer, MeshBuffers};
+use super::pbr;
 use super::types;
 use std::alloc::{dealloc, Layout};
 use std::ffi::CStr;
-use std::os::raw::c_char;
+use std::os::raw::{c_char, c_void};
 use std::ptr;
 
 #[repr(C)]
@@ -19,6 +23,19 @@ pub struct Vector2 {
     y: f32,
 }
 
+#[repr(C)]
+pub struct Quaternion {
+    x: f32,
+    y: f32,
+    z: f32,
+    w: f32,
+}
+
+#[repr(C)]
+pub struct Matrix4 {
+    m: [f32; 16],
+}
+
 #[repr(C)]
 pub struct Color {
     x: u8,
@@ -54,6 +71,124 @@ pub extern "C" fn xps_load_model(
     return boxed;
 }
 
+#[no_mangle]
+pub extern "C" fn xps_create_import_parameters() -> Box<types::ImportParameters> {
+    Box::new(types::ImportParameters::default())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_delete_import_parameters(params: Box<types::ImportParameters>) {
+    let p = Box::into_raw(params);
+    unsafe {
+        ptr::drop_in_place(p);
+        dealloc(p as *mut u8, Layout::new::<types::ImportParameters>());
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_flip_uv(params: *mut types::ImportParameters, value: i32) {
+    let _params = unsafe { &mut *params };
+    _params.flip_uv = value != 0;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_reverse_winding(
+    params: *mut types::ImportParameters,
+    value: i32,
+) {
+    let _params = unsafe { &mut *params };
+    _params.reverse_winding = value != 0;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_optimize_vertex_cache(
+    params: *mut types::ImportParameters,
+    value: i32,
+) {
+    let _params = unsafe { &mut *params };
+    _params.optimize_vertex_cache = value != 0;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_optimize_overdraw(
+    params: *mut types::ImportParameters,
+    value: i32,
+) {
+    let _params = unsafe { &mut *params };
+    _params.optimize_overdraw = value != 0;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_normalize_weights(
+    params: *mut types::ImportParameters,
+    value: i32,
+    threshold: f32,
+    max_bone_influences: i32,
+) {
+    let _params = unsafe { &mut *params };
+    _params.normalize_weights = value != 0;
+    _params.weight_threshold = threshold;
+    _params.max_bone_influences = max_bone_influences.clamp(1, u8::MAX as i32) as u8;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_orient_bones(params: *mut types::ImportParameters, value: i32) {
+    let _params = unsafe { &mut *params };
+    _params.orient_bones = value != 0;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_axes(
+    params: *mut types::ImportParameters,
+    up_axis: i32,
+    forward_axis: i32,
+    left_handed: i32,
+) {
+    let _params = unsafe { &mut *params };
+    if let (Some(up), Some(forward)) = (Axis::from_index(up_axis), Axis::from_index(forward_axis)) {
+        _params.up_axis = up;
+        _params.forward_axis = forward;
+    }
+    _params.handedness = if left_handed == 0 {
+        Handedness::Right
+    } else {
+        Handedness::Left
+    };
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_scale(params: *mut types::ImportParameters, scale: f32) {
+    let _params = unsafe { &mut *params };
+    _params.scale = scale;
+}
+
+#[no_mangle]
+pub extern "C" fn xps_set_import_mesh_buffers(params: *mut types::ImportParameters, value: i32) {
+    let _params = unsafe { &mut *params };
+    _params.mesh_buffers = value != 0;
+}
+
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub extern "C" fn xps_load_model_with_parameters(
+    filename: *const c_char,
+    bone_naming_format: super::bone_naming::BoneNaming,
+    params: *const types::ImportParameters,
+) -> Box<types::Data> {
+    let c_str = unsafe { CStr::from_ptr(filename) };
+    let _params = unsafe { &*params };
+    match c_str.to_str() {
+        Ok(s) => match open_with_parameters(s, bone_naming_format, *_params) {
+            Ok(x) => Box::new(x),
+            Err(x) => Box::new(types::Data {
+                error: x,
+                ..types::Data::default()
+            }),
+        },
+        Err(_) => Box::new(types::Data::default()),
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn xps_get_error(model: *mut types::Data) -> XpsError {
     let mut _model = unsafe { &mut *model };
@@ -109,6 +244,112 @@ pub extern "C" fn xps_get_mesh_name(model: *mut types::Data, mesh_index: i32) ->
     _model.meshes[mesh_index as usize].name.as_ptr()
 }
 
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_full_name(model: *mut types::Data, mesh_index: i32) -> *const c_char {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].full_name.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_item_name(model: *mut types::Data, mesh_index: i32) -> *const c_char {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].item.name.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_is_optional(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].item.optional as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_visible_by_default(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].item.visible_by_default as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_has_camera(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].camera.is_some() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_camera_name(model: *mut types::Data, mesh_index: i32) -> *const c_char {
+    let _model = unsafe { &*model };
+    match &_model.meshes[mesh_index as usize].camera {
+        Some(x) => x.camera.as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_camera_target_count(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    match &_model.meshes[mesh_index as usize].camera {
+        Some(x) => x.targets.len() as i32,
+        None => 0,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_camera_target_name(
+    model: *mut types::Data,
+    mesh_index: i32,
+    target_index: i32,
+) -> *const c_char {
+    let _model = unsafe { &*model };
+    match &_model.meshes[mesh_index as usize].camera {
+        Some(x) => x.targets[target_index as usize].as_ptr(),
+        None => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_camera_target_bone(
+    model: *mut types::Data,
+    mesh_index: i32,
+    target_index: i32,
+) -> i32 {
+    let _model = unsafe { &*model };
+    match &_model.meshes[mesh_index as usize].camera {
+        Some(x) => x.bone_indices[target_index as usize].map_or(-1, |x| x as i32),
+        None => -1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_optional_item_count(model: *mut types::Data) -> i32 {
+    let _model = unsafe { &*model };
+    _model.optional_items.len() as i32
+}
+
+// The name is owned by the item's first mesh.
+#[no_mangle]
+pub extern "C" fn xps_get_optional_item_name(model: *mut types::Data, item_index: i32) -> *const c_char {
+    let _model = unsafe { &*model };
+    let mesh_index = _model.optional_items[item_index as usize].mesh_indices[0];
+    _model.meshes[mesh_index].item.name.as_ptr()
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_optional_item_visible_by_default(model: *mut types::Data, item_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.optional_items[item_index as usize].visible_by_default as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_optional_item_mesh_count(model: *mut types::Data, item_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.optional_items[item_index as usize].mesh_indices.len() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_optional_item_mesh_index(model: *mut types::Data, item_index: i32, index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.optional_items[item_index as usize].mesh_indices[index as usize] as i32
+}
+
 #[no_mangle]
 pub extern "C" fn xps_get_uv_layers(model: *mut types::Data, mesh_index: i32) -> i32 {
     let mut _model = unsafe { &mut *model };
@@ -118,7 +359,7 @@ pub extern "C" fn xps_get_uv_layers(model: *mut types::Data, mesh_index: i32) ->
 #[no_mangle]
 pub extern "C" fn xps_get_vertex_count(model: *mut types::Data, mesh_index: i32) -> i32 {
     let mut _model = unsafe { &mut *model };
-    _model.meshes[mesh_index as usize].vertices.len() as i32
+    _model.meshes[mesh_index as usize].vertex_count() as i32
 }
 
 #[no_mangle]
@@ -149,6 +390,18 @@ pub extern "C" fn xps_get_texture_filename(
         .as_ptr()
 }
 
+#[no_mangle]
+pub extern "C" fn xps_get_texture_path(
+    model: *mut types::Data,
+    mesh_index: i32,
+    texture_index: i32,
+) -> *const c_char {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].textures[texture_index as usize]
+        .path
+        .as_ptr()
+}
+
 #[no_mangle]
 pub extern "C" fn xps_get_texture_uv_layer(
     model: *mut types::Data,
@@ -159,6 +412,30 @@ pub extern "C" fn xps_get_texture_uv_layer(
     _model.meshes[mesh_index as usize].textures[texture_index as usize].uv_layer as i32
 }
 
+#[no_mangle]
+pub extern "C" fn xps_get_texture_slot(
+    model: *mut types::Data,
+    mesh_index: i32,
+    texture_index: i32,
+) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].textures[texture_index as usize].slot as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_texture_index_by_slot(
+    model: *mut types::Data,
+    mesh_index: i32,
+    slot: i32,
+) -> i32 {
+    let _model = unsafe { &*model };
+    let mesh = &_model.meshes[mesh_index as usize];
+    TextureSlot::from_index(slot)
+        .and_then(|x| mesh.textures.iter().position(|y| y.slot == x))
+        .map(|x| x as i32)
+        .unwrap_or(-1)
+}
+
 #[no_mangle]
 pub extern "C" fn xps_get_mesh_index_count(model: *mut types::Data, mesh_index: i32) -> i32 {
     let mut _model = unsafe { &mut *model };
@@ -182,10 +459,11 @@ pub extern "C" fn xps_get_vertex_position(
     vertex_index: i32,
 ) -> Vector3 {
     let mut _model = unsafe { &mut *model };
+    let position = _model.meshes[mesh_index as usize].vertex_position(vertex_index as usize);
     Vector3 {
-        x: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].position[0],
-        y: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].position[1],
-        z: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].position[2],
+        x: position[0],
+        y: position[1],
+        z: position[2],
     }
 }
 
@@ -196,10 +474,11 @@ pub extern "C" fn xps_get_vertex_normal(
     vertex_index: i32,
 ) -> Vector3 {
     let mut _model = unsafe { &mut *model };
+    let normal = _model.meshes[mesh_index as usize].vertex_normal(vertex_index as usize);
     Vector3 {
-        x: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].normal[0],
-        y: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].normal[1],
-        z: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].normal[2],
+        x: normal[0],
+        y: normal[1],
+        z: normal[2],
     }
 }
 
@@ -210,11 +489,12 @@ pub extern "C" fn xps_get_vertex_color(
     vertex_index: i32,
 ) -> Color {
     let mut _model = unsafe { &mut *model };
+    let color = _model.meshes[mesh_index as usize].vertex_color(vertex_index as usize);
     Color {
-        x: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].color[0],
-        y: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].color[1],
-        z: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].color[2],
-        w: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].color[3],
+        x: color[0],
+        y: color[1],
+        z: color[2],
+        w: color[3],
     }
 }
 
@@ -226,12 +506,10 @@ pub extern "C" fn xps_get_vertex_uv(
     layer_id: i32,
 ) -> Vector2 {
     let mut _model = unsafe { &mut *model };
-    Vector2 {
-        x: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].uv[layer_id as usize]
-            [0],
-        y: _model.meshes[mesh_index as usize].vertices[vertex_index as usize].uv[layer_id as usize]
-            [1],
-    }
+    let uv = _model.meshes[mesh_index as usize]
+        .vertex_uv(vertex_index as usize, layer_id as usize)
+        .unwrap_or_default();
+    Vector2 { x: uv[0], y: uv[1] }
 }
 
 #[no_mangle]
@@ -242,8 +520,8 @@ pub extern "C" fn xps_get_vertex_bone_index(
     weight_id: i32,
 ) -> i32 {
     let mut _model = unsafe { &mut *model };
-    _model.meshes[mesh_index as usize].vertices[vertex_index as usize].bone_weights
-        [weight_id as usize]
+    _model.meshes[mesh_index as usize]
+        .vertex_bone_weight(vertex_index as usize, weight_id as usize)
         .id as i32
 }
 
@@ -255,11 +533,21 @@ pub extern "C" fn xps_get_vertex_bone_weight(
     weight_id: i32,
 ) -> f32 {
     let mut _model = unsafe { &mut *model };
-    _model.meshes[mesh_index as usize].vertices[vertex_index as usize].bone_weights
-        [weight_id as usize]
+    _model.meshes[mesh_index as usize]
+        .vertex_bone_weight(vertex_index as usize, weight_id as usize)
         .weight
 }
 
+#[no_mangle]
+pub extern "C" fn xps_get_vertex_bone_weight_count(
+    model: *mut types::Data,
+    mesh_index: i32,
+    vertex_index: i32,
+) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].vertex_bone_weight_count(vertex_index as usize) as i32
+}
+
 #[no_mangle]
 fn xps_get_render_group_alpha(model: *mut types::Data, mesh_index: i32) -> i32 {
     let mut _model = unsafe { &mut *model };
@@ -310,10 +598,27 @@ fn xps_get_render_group_spec1_rep(model: *mut types::Data, mesh_index: i32) -> i
     }
 }
 
+#[no_mangle]
+pub extern "C" fn xps_get_render_group_number(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].render_group.id.number()
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_render_group_known(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    match _model.meshes[mesh_index as usize].render_group.id {
+        RenderGroupId::Known(_) => 1,
+        RenderGroupId::Unknown(_) => 0,
+    }
+}
+
 #[no_mangle]
 fn xps_get_render_group_texture_count(model: *mut types::Data, mesh_index: i32) -> i32 {
     let mut _model = unsafe { &mut *model };
-    _model.meshes[mesh_index as usize].render_group.tex_count
+    _model.meshes[mesh_index as usize]
+        .render_group
+        .texture_count() as i32
 }
 
 #[no_mangle]
@@ -325,6 +630,433 @@ fn xps_get_render_group_texture_type(
     let mut _model = unsafe { &mut *model };
     _model.meshes[mesh_index as usize]
         .render_group
-        .texture_types[texture_type_index as usize]
+        .texture_slot(texture_type_index as usize)
+        .c_name()
         .as_ptr()
 }
+
+fn bounds_min(aabb: Option<super::bounds::Aabb>) -> Vector3 {
+    match aabb {
+        Some(x) => Vector3 {
+            x: x.min[0],
+            y: x.min[1],
+            z: x.min[2],
+        },
+        None => Vector3 {
+            x: 0_f32,
+            y: 0_f32,
+            z: 0_f32,
+        },
+    }
+}
+
+fn bounds_max(aabb: Option<super::bounds::Aabb>) -> Vector3 {
+    match aabb {
+        Some(x) => Vector3 {
+            x: x.max[0],
+            y: x.max[1],
+            z: x.max[2],
+        },
+        None => Vector3 {
+            x: 0_f32,
+            y: 0_f32,
+            z: 0_f32,
+        },
+    }
+}
+
+fn sphere_center(sphere: Option<super::bounds::Sphere>) -> Vector3 {
+    match sphere {
+        Some(x) => Vector3 {
+            x: x.center[0],
+            y: x.center[1],
+            z: x.center[2],
+        },
+        None => Vector3 {
+            x: 0_f32,
+            y: 0_f32,
+            z: 0_f32,
+        },
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bounds_min(model: *mut types::Data, mesh_index: i32) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_min(_model.meshes[mesh_index as usize].aabb())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bounds_max(model: *mut types::Data, mesh_index: i32) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_max(_model.meshes[mesh_index as usize].aabb())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bounding_sphere_center(
+    model: *mut types::Data,
+    mesh_index: i32,
+) -> Vector3 {
+    let _model = unsafe { &*model };
+    sphere_center(_model.meshes[mesh_index as usize].bounding_sphere())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bounding_sphere_radius(
+    model: *mut types::Data,
+    mesh_index: i32,
+) -> f32 {
+    let _model = unsafe { &*model };
+    match _model.meshes[mesh_index as usize].bounding_sphere() {
+        Some(x) => x.radius,
+        None => 0_f32,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_model_bounds_min(model: *mut types::Data) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_min(_model.aabb())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_model_bounds_max(model: *mut types::Data) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_max(_model.aabb())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_model_bounding_sphere_center(model: *mut types::Data) -> Vector3 {
+    let _model = unsafe { &*model };
+    sphere_center(_model.bounding_sphere())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_model_bounding_sphere_radius(model: *mut types::Data) -> f32 {
+    let _model = unsafe { &*model };
+    match _model.bounding_sphere() {
+        Some(x) => x.radius,
+        None => 0_f32,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_has_bone_bounds(model: *mut types::Data, index: i32, threshold: f32) -> i32 {
+    let _model = unsafe { &*model };
+    if _model.bone_aabb(index as usize, threshold).is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_bounds_min(
+    model: *mut types::Data,
+    index: i32,
+    threshold: f32,
+) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_min(_model.bone_aabb(index as usize, threshold))
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_bounds_max(
+    model: *mut types::Data,
+    index: i32,
+    threshold: f32,
+) -> Vector3 {
+    let _model = unsafe { &*model };
+    bounds_max(_model.bone_aabb(index as usize, threshold))
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_max_influences(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].max_influences() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_specular_amount(model: *mut types::Data, mesh_index: i32) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .render_parameters
+        .specular_amount
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bump1_uv_scale(model: *mut types::Data, mesh_index: i32) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .render_parameters
+        .bump1_uv_scale
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_bump2_uv_scale(model: *mut types::Data, mesh_index: i32) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .render_parameters
+        .bump2_uv_scale
+}
+
+fn material_texture(
+    model: *mut types::Data,
+    mesh_index: i32,
+    slot: i32,
+) -> Option<pbr::MaterialTexture> {
+    let _model = unsafe { &*model };
+    let material = _model.meshes[mesh_index as usize].pbr_material();
+    pbr::MaterialSlot::from_index(slot).and_then(|x| material.texture(x).cloned())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_texture(
+    model: *mut types::Data,
+    mesh_index: i32,
+    slot: i32,
+) -> i32 {
+    material_texture(model, mesh_index, slot)
+        .map(|x| x.texture_index as i32)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_texture_tiling(
+    model: *mut types::Data,
+    mesh_index: i32,
+    slot: i32,
+) -> f32 {
+    material_texture(model, mesh_index, slot)
+        .map(|x| x.tiling)
+        .unwrap_or(1_f32)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_alpha_mode(model: *mut types::Data, mesh_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    match _model.meshes[mesh_index as usize].pbr_material().alpha_mode {
+        pbr::AlphaMode::Opaque => 0,
+        pbr::AlphaMode::Blend => 1,
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_metallic(model: *mut types::Data, mesh_index: i32) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .pbr_material()
+        .metallic_factor
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_roughness(model: *mut types::Data, mesh_index: i32) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .pbr_material()
+        .roughness_factor
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_specular_intensity(
+    model: *mut types::Data,
+    mesh_index: i32,
+) -> f32 {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize]
+        .pbr_material()
+        .specular_intensity
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_material_emissive_factor(
+    model: *mut types::Data,
+    mesh_index: i32,
+) -> Vector3 {
+    let _model = unsafe { &*model };
+    let factor = _model.meshes[mesh_index as usize]
+        .pbr_material()
+        .emissive_factor;
+    Vector3 {
+        x: factor[0],
+        y: factor[1],
+        z: factor[2],
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_warning_count(model: *mut types::Data) -> i32 {
+    let _model = unsafe { &*model };
+    _model.warnings.len() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_warning_kind(model: *mut types::Data, index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.warnings[index as usize].kind as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_warning_mesh_index(model: *mut types::Data, index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.warnings[index as usize]
+        .mesh_index
+        .map(|x| x as i32)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_warning_message(model: *mut types::Data, index: i32) -> *const c_char {
+    let _model = unsafe { &*model };
+    _model.warnings[index as usize].message.as_ptr()
+}
+
+fn matrix4(m: super::math::Mat4) -> Matrix4 {
+    let mut out = Matrix4 { m: [0_f32; 16] };
+    for (column, values) in m.iter().enumerate() {
+        out.m[column * 4..column * 4 + 4].copy_from_slice(values);
+    }
+    out
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_local_position(model: *mut types::Data, index: i32) -> Vector3 {
+    let _model = unsafe { &*model };
+    let local = _model.bone_local_translation(index as usize);
+    Vector3 {
+        x: local[0],
+        y: local[1],
+        z: local[2],
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_bind_matrix(model: *mut types::Data, index: i32) -> Matrix4 {
+    let _model = unsafe { &*model };
+    matrix4(_model.bone_world_matrix(index as usize))
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_inverse_bind_matrix(model: *mut types::Data, index: i32) -> Matrix4 {
+    let _model = unsafe { &*model };
+    matrix4(super::math::mat4_inverse_rigid(
+        &_model.bone_world_matrix(index as usize),
+    ))
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_sorted_bone_index(model: *mut types::Data, order_index: i32) -> i32 {
+    let _model = unsafe { &*model };
+    if order_index < 0 {
+        return -1;
+    }
+    _model
+        .bone_order
+        .get(order_index as usize)
+        .map(|x| *x as i32)
+        .unwrap_or(-1)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_rest_rotation(model: *mut types::Data, index: i32) -> Quaternion {
+    let _model = unsafe { &*model };
+    let q = _model.bones[index as usize].rest_rotation;
+    Quaternion {
+        x: q[0],
+        y: q[1],
+        z: q[2],
+        w: q[3],
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_bone_local_rotation(model: *mut types::Data, index: i32) -> Quaternion {
+    let _model = unsafe { &*model };
+    let q = _model.bone_local_rotation(index as usize);
+    Quaternion {
+        x: q[0],
+        y: q[1],
+        z: q[2],
+        w: q[3],
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_header_version_mayor(model: *mut types::Data) -> i32 {
+    let _model = unsafe { &*model };
+    _model.header.version_mayor as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_header_version_minor(model: *mut types::Data) -> i32 {
+    let _model = unsafe { &*model };
+    _model.header.version_minor as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_header_flag(model: *mut types::Data, key: i32) -> i32 {
+    let _model = unsafe { &*model };
+    _model.header.flags.value(key as u32).map_or(-1, |x| x as i32)
+}
+
+fn mesh_buffers<'a>(model: *mut types::Data, mesh_index: i32) -> Option<&'a MeshBuffers> {
+    let _model = unsafe { &*model };
+    _model.meshes[mesh_index as usize].buffers.as_ref()
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_has_buffers(model: *mut types::Data, mesh_index: i32) -> i32 {
+    mesh_buffers(model, mesh_index).is_some() as i32
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_positions(model: *mut types::Data, mesh_index: i32) -> *const f32 {
+    mesh_buffers(model, mesh_index).map_or(std::ptr::null(), |x| x.positions.as_ptr() as *const f32)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_normals(model: *mut types::Data, mesh_index: i32) -> *const f32 {
+    mesh_buffers(model, mesh_index).map_or(std::ptr::null(), |x| x.normals.as_ptr() as *const f32)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_colors(model: *mut types::Data, mesh_index: i32) -> *const u8 {
+    mesh_buffers(model, mesh_index).map_or(std::ptr::null(), |x| x.colors.as_ptr() as *const u8)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_uvs(model: *mut types::Data, mesh_index: i32, layer_id: i32) -> *const f32 {
+    match mesh_buffers(model, mesh_index).and_then(|x| x.uvs.get(layer_id as usize)) {
+        Some(x) => x.as_ptr() as *const f32,
+        None => std::ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_influences(model: *mut types::Data, mesh_index: i32) -> i32 {
+    mesh_buffers(model, mesh_index).map_or(0, |x| x.influences as i32)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_joints(model: *mut types::Data, mesh_index: i32) -> *const i16 {
+    mesh_buffers(model, mesh_index).map_or(std::ptr::null(), |x| x.joints.as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_weights(model: *mut types::Data, mesh_index: i32) -> *const f32 {
+    mesh_buffers(model, mesh_index).map_or(std::ptr::null(), |x| x.weights.as_ptr())
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_index_size(model: *mut types::Data, mesh_index: i32) -> i32 {
+    mesh_buffers(model, mesh_index).map_or(0, |x| x.indices.index_size() as i32)
+}
+
+#[no_mangle]
+pub extern "C" fn xps_get_mesh_indices(model: *mut types::Data, mesh_index: i32) -> *const c_void {
+    match mesh_buffers(model, mesh_index).map(|x| &x.indices) {
+        Some(IndexBuffer::U16(x)) => x.as_ptr() as *const c_void,
+        Some(IndexBuffer::U32(x)) => x.as_ptr() as *const c_void,
+        None => std::ptr::null(),
+    }
+}
@@ -1,11 +1,32 @@
 mod ascii;
 mod binary;
+pub mod batching;
+pub mod bounds;
+pub mod camera;
 pub mod constants;
+pub mod conversion;
 pub mod error_types;
 mod file_input;
+pub mod geometry;
+pub mod header_writer;
 pub mod interface;
 pub mod loader;
+pub mod math;
+pub mod mesh_buffers;
 pub mod material;
 pub mod mesh_name_parser;
+pub mod optimize;
+pub mod optional_items;
+pub mod pbr;
+pub mod simplify;
+pub mod skeleton;
+pub mod skinning;
+pub mod smd;
+#[cfg(feature = "textures")]
+pub mod texture_codec;
+pub mod texture_resolver;
+#[cfg(test)]
+mod test_util;
 pub mod types;
+pub mod weld;
 pub mod bone_naming;
@@ -2,9 +2,17 @@ use super::ascii;
 use super::binary;
 use super::bone_naming;
 use super::bone_naming::{BoneNaming, Converter};
-use super::error_types::XpsError;
+use super::camera;
+use super::conversion;
+use super::error_types::{WarningKind, XpsError, XpsWarning};
+use super::material;
+use super::mesh_name_parser;
+use super::optimize;
+use super::skeleton;
+use super::skinning;
 use super::types;
 use super::types::ImportParameters;
+use std::collections::HashMap;
 
 pub fn open(
     filename: &str,
@@ -15,7 +23,16 @@ pub fn open(
     let import_parameters = ImportParameters {
         flip_uv: flip_uv,
         reverse_winding: reverse_winding,
+        ..ImportParameters::default()
     };
+    open_with_parameters(filename, bone_naming, import_parameters)
+}
+
+pub fn open_with_parameters(
+    filename: &str,
+    bone_naming: super::bone_naming::BoneNaming,
+    import_parameters: ImportParameters,
+) -> Result<types::Data, XpsError> {
     let mut model = {
         if filename.ends_with(".ascii") {
             {
@@ -36,6 +53,47 @@ pub fn open(
         }
     };
 
+    model.warnings = material::render_group_warnings(&model.meshes);
+    model
+        .warnings
+        .extend(material::texture_count_warnings(&model.meshes));
+    model
+        .warnings
+        .extend(mesh_name_parser::mesh_name_warnings(&model.meshes));
+    let camera_warnings = camera::resolve_camera_targets(&mut model);
+    model.warnings.extend(camera_warnings);
+    model.update_optional_items();
+    model.update_bone_order();
+
+    if import_parameters.orient_bones {
+        skeleton::orient_bones(&mut model, false);
+    }
+
+    match conversion::AxisConversion::from_parameters(&import_parameters) {
+        Some(x) => conversion::convert_model(&mut model, &x),
+        None => model.warnings.push(XpsWarning::new(
+            WarningKind::InvalidAxisConversion,
+            None,
+            String::from("axes are not perpendicular or scale is invalid; model left unconverted"),
+        )),
+    }
+
+    if import_parameters.normalize_weights {
+        skinning::normalize_model_weights(
+            &mut model,
+            import_parameters.weight_threshold,
+            import_parameters.max_bone_influences as usize,
+        );
+    }
+
+    if import_parameters.optimize_vertex_cache || import_parameters.optimize_overdraw {
+        optimize::optimize_model(&mut model, import_parameters.optimize_overdraw);
+    }
+
+    for mesh in model.meshes.iter_mut() {
+        mesh.update_index_buffer();
+    }
+
     match bone_naming {
         BoneNaming::Mecanim => {
             let conv = Converter::new();
@@ -56,3 +114,72 @@ pub fn open(
     }
     Ok(model)
 }
+
+// Pass the parameters the model was loaded with so the pose ends up in the
+// same axes and scale as the model.
+pub fn open_pose(
+    filename: &str,
+    import_parameters: ImportParameters,
+) -> Result<HashMap<String, types::BonePose>, XpsError> {
+    let mut pose = match std::fs::read_to_string(filename) {
+        Ok(x) => ascii::pose_data(&x.replace("\r", "")),
+        Err(_) => return Err(XpsError::StreamNotOpened),
+    };
+    match conversion::AxisConversion::from_parameters(&import_parameters) {
+        Some(x) => conversion::convert_pose(&mut pose, &x),
+        None => return Err(XpsError::InvalidArgument),
+    }
+    Ok(pose)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn open_pose_applies_model_conversion() {
+        let filename = std::env::temp_dir().join("xpsimport_open_pose_test.pose");
+        std::fs::write(&filename, "root ground: 0 1 0 0 0 0 1 1 1\r\n").unwrap();
+        let parameters = ImportParameters {
+            up_axis: conversion::Axis::PositiveZ,
+            forward_axis: conversion::Axis::NegativeY,
+            scale: 2_f32,
+            ..ImportParameters::default()
+        };
+        let pose = open_pose(filename.to_str().unwrap(), parameters).unwrap();
+        let _ = std::fs::remove_file(&filename);
+        let expected = conversion::AxisConversion::from_parameters(&parameters)
+            .unwrap()
+            .point([0_f32, 1_f32, 0_f32]);
+        let delta = pose["root ground"].coordinate_delta;
+        assert!(delta
+            .iter()
+            .zip(expected.iter())
+            .all(|(a, b)| (a - b).abs() < 1e-5));
+        assert!((delta[2] - 2_f32).abs() < 1e-5);
+        assert_eq!(pose["root ground"].scale, [1_f32; 3]);
+    }
+
+    #[test]
+    fn invalid_conversion_is_reported() {
+        let parameters = ImportParameters {
+            up_axis: conversion::Axis::PositiveY,
+            forward_axis: conversion::Axis::NegativeY,
+            ..ImportParameters::default()
+        };
+        let filename = std::env::temp_dir().join("xpsimport_invalid_conversion_test.mesh.ascii");
+        std::fs::write(&filename, "0 # bones\n0 # meshes\n").unwrap();
+        let model =
+            open_with_parameters(filename.to_str().unwrap(), BoneNaming::Default, parameters);
+        let _ = std::fs::remove_file(&filename);
+        let model = model.unwrap();
+        assert_eq!(model.warnings.len(), 1);
+        assert_eq!(model.warnings[0].kind, WarningKind::InvalidAxisConversion);
+
+        let filename = std::env::temp_dir().join("xpsimport_invalid_conversion_test.pose");
+        std::fs::write(&filename, "root ground: 0 1 0 0 0 0 1 1 1\r\n").unwrap();
+        let pose = open_pose(filename.to_str().unwrap(), parameters);
+        let _ = std::fs::remove_file(&filename);
+        assert!(pose.is_err());
+    }
+}
@@ -1,25 +1,122 @@
+use super::error_types::{WarningKind, XpsWarning};
+use super::types::{Mesh, Texture};
+use std::ffi::CStr;
 use std::vec::Vec;
+
+// Texture roles used by render groups. `name` keeps the spelling XPS uses.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextureSlot {
+   Diffuse,
+   Lightmap,
+   Bumpmap,
+   Mask,
+   Bump1,
+   Bump2,
+   Specular,
+   Environment,
+   Emission,
+   EmissionMiniMap,
+   Unknown,
+}
+
+impl TextureSlot {
+   pub const ALL: [TextureSlot; 11] = [
+      TextureSlot::Diffuse,
+      TextureSlot::Lightmap,
+      TextureSlot::Bumpmap,
+      TextureSlot::Mask,
+      TextureSlot::Bump1,
+      TextureSlot::Bump2,
+      TextureSlot::Specular,
+      TextureSlot::Environment,
+      TextureSlot::Emission,
+      TextureSlot::EmissionMiniMap,
+      TextureSlot::Unknown,
+   ];
+
+   pub fn from_index(index: i32) -> Option<TextureSlot> {
+      TextureSlot::ALL.get(index as usize).cloned()
+   }
+
+   pub fn from_name(name: &str) -> TextureSlot {
+      TextureSlot::ALL
+         .iter()
+         .find(|x| x.name() == name)
+         .cloned()
+         .unwrap_or(TextureSlot::Unknown)
+   }
+
+   fn bytes(self) -> &'static [u8] {
+      match self {
+         TextureSlot::Diffuse => b"diffuse\0",
+         TextureSlot::Lightmap => b"lightmap\0",
+         TextureSlot::Bumpmap => b"bumpmap\0",
+         TextureSlot::Mask => b"mask\0",
+         TextureSlot::Bump1 => b"bump1\0",
+         TextureSlot::Bump2 => b"bump2\0",
+         TextureSlot::Specular => b"specular\0",
+         TextureSlot::Environment => b"enviroment\0",
+         TextureSlot::Emission => b"emission\0",
+         TextureSlot::EmissionMiniMap => b"emission_mini_map\0",
+         TextureSlot::Unknown => b"\0",
+      }
+   }
+
+   pub fn name(self) -> &'static str {
+      let bytes = self.bytes();
+      std::str::from_utf8(&bytes[..bytes.len() - 1]).unwrap_or("")
+   }
+
+   pub fn c_name(self) -> &'static CStr {
+      CStr::from_bytes_with_nul(self.bytes()).unwrap_or_default()
+   }
+}
+
+// Render group number as read from the mesh name. Numbers this importer has
+// no definition for get the six texture diffuse + mask layout.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum RenderGroupId {
+   Known(i32),
+   Unknown(i32),
+}
+
+impl RenderGroupId {
+   pub fn number(self) -> i32 {
+      match self {
+         RenderGroupId::Known(x) | RenderGroupId::Unknown(x) => x,
+      }
+   }
+}
+
+#[derive(Clone, PartialEq)]
 pub struct RenderGroup {
+   pub id: RenderGroupId,
    pub alpha: bool,
    pub posable: bool,
    pub specular: String,
    pub bump1_rep: bool,
    pub bump2_rep: bool,
    pub spec1_rep: bool,
-   pub tex_count: i32,
-   pub texture_types: Vec<std::ffi::CString>,
+   pub texture_slots: Vec<TextureSlot>,
 }
 
 impl RenderGroup {
    pub fn new(render_group_num: i32) -> RenderGroup {
+      let mut known = true;
       let mut alpha = false;
       let mut posable = true;
       let mut specular = "Yes";
       let mut bump1_rep = true;
       let mut bump2_rep = true;
       let mut spec1_rep = false;
-      let mut tex_count = 6;
-      let mut texture_types = vec!["diffuse", "mask", "mask", "mask", "mask", "mask"];
+      let mut texture_slots = vec![
+         TextureSlot::Diffuse,
+         TextureSlot::Mask,
+         TextureSlot::Mask,
+         TextureSlot::Mask,
+         TextureSlot::Mask,
+         TextureSlot::Mask,
+      ];
 
       match render_group_num {
          1 => {
@@ -28,8 +125,14 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 6;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap", "mask", "bump1", "bump2"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+            ];
          }
          2 => {
             alpha = false;
@@ -37,8 +140,11 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+            ];
          }
          3 => {
             alpha = false;
@@ -46,8 +152,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "lightmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Lightmap];
          }
          4 => {
             alpha = false;
@@ -55,8 +160,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          5 => {
             alpha = false;
@@ -64,8 +168,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          6 => {
             alpha = true;
@@ -73,8 +176,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          7 => {
             alpha = true;
@@ -82,8 +184,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          8 => {
             alpha = true;
@@ -91,8 +192,11 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+            ];
          }
          9 => {
             alpha = true;
@@ -100,8 +204,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "lightmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Lightmap];
          }
          10 => {
             alpha = false;
@@ -109,8 +212,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          11 => {
             alpha = false;
@@ -118,8 +220,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          12 => {
             alpha = true;
@@ -127,8 +228,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          13 => {
             alpha = false;
@@ -136,8 +236,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          14 => {
             alpha = false;
@@ -145,8 +244,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          15 => {
             alpha = true;
@@ -154,8 +252,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "bumpmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Bumpmap];
          }
          16 => {
             alpha = false;
@@ -163,8 +260,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          17 => {
             alpha = false;
@@ -172,8 +268,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "lightmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Lightmap];
          }
          18 => {
             alpha = true;
@@ -181,8 +276,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          19 => {
             alpha = true;
@@ -190,8 +284,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 2;
-            texture_types = vec!["diffuse", "lightmap"];
+            texture_slots = vec![TextureSlot::Diffuse, TextureSlot::Lightmap];
          }
          20 => {
             alpha = true;
@@ -199,8 +292,14 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 6;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap", "mask", "bump1", "bump2"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+            ];
          }
          21 => {
             alpha = true;
@@ -208,8 +307,7 @@ impl RenderGroup {
             specular = "No";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          22 => {
             alpha = false;
@@ -217,9 +315,14 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 7;
-            texture_types = vec![
-               "diffuse", "lightmap", "bumpmap", "mask", "bump1", "bump2", "specular",
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+               TextureSlot::Specular,
             ];
          }
          23 => {
@@ -228,9 +331,14 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 7;
-            texture_types = vec![
-               "diffuse", "lightmap", "bumpmap", "mask", "bump1", "bump2", "specular",
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+               TextureSlot::Specular,
             ];
          }
          24 => {
@@ -239,8 +347,12 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
          25 => {
             alpha = true;
@@ -248,8 +360,12 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "lightmap", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Lightmap,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
          26 => {
             alpha = false;
@@ -257,8 +373,12 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "bumpmap", "enviroment", "mask"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Environment,
+               TextureSlot::Mask,
+            ];
          }
          27 => {
             alpha = true;
@@ -266,8 +386,12 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "bumpmap", "enviroment", "mask"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Environment,
+               TextureSlot::Mask,
+            ];
          }
          28 => {
             alpha = false;
@@ -275,8 +399,14 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 6;
-            texture_types = vec!["diffuse", "bumpmap", "mask", "bump1", "bump2", "enviroment"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+               TextureSlot::Environment,
+            ];
          }
          29 => {
             alpha = true;
@@ -284,8 +414,14 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = true;
-            tex_count = 6;
-            texture_types = vec!["diffuse", "bumpmap", "mask", "bump1", "bump2", "enviroment"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Mask,
+               TextureSlot::Bump1,
+               TextureSlot::Bump2,
+               TextureSlot::Environment,
+            ];
          }
          30 => {
             alpha = false;
@@ -293,8 +429,11 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "emission"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Emission,
+            ];
          }
          31 => {
             alpha = true;
@@ -302,8 +441,11 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "emission"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Emission,
+            ];
          }
          32 => {
             alpha = false;
@@ -311,8 +453,7 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
          33 => {
             alpha = true;
@@ -320,19 +461,19 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 1;
-            texture_types = vec!["diffuse"];
+            texture_slots = vec![TextureSlot::Diffuse];
          }
-         34 => {}
-         35 => {}
          36 => {
             alpha = false;
             posable = true;
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "emission_mini_map"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::EmissionMiniMap,
+            ];
          }
          37 => {
             alpha = true;
@@ -340,8 +481,11 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "emission_mini_map"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::EmissionMiniMap,
+            ];
          }
          38 => {
             alpha = false;
@@ -349,8 +493,12 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "bumpmap", "specular", "emission"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+               TextureSlot::Emission,
+            ];
          }
          39 => {
             alpha = true;
@@ -358,8 +506,12 @@ impl RenderGroup {
             specular = "Yes intensity";
             bump1_rep = true;
             bump2_rep = false;
-            tex_count = 4;
-            texture_types = vec!["diffuse", "bumpmap", "specular", "emission"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+               TextureSlot::Emission,
+            ];
          }
          40 => {
             alpha = false;
@@ -367,8 +519,11 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
          41 => {
             alpha = true;
@@ -376,8 +531,11 @@ impl RenderGroup {
             specular = "Yes";
             bump1_rep = false;
             bump2_rep = false;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
          42 => {
             alpha = false;
@@ -386,8 +544,11 @@ impl RenderGroup {
             bump1_rep = false;
             bump2_rep = false;
             spec1_rep = true;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
          43 => {
             alpha = true;
@@ -396,25 +557,132 @@ impl RenderGroup {
             bump1_rep = false;
             bump2_rep = false;
             spec1_rep = true;
-            tex_count = 3;
-            texture_types = vec!["diffuse", "bumpmap", "specular"];
+            texture_slots = vec![
+               TextureSlot::Diffuse,
+               TextureSlot::Bumpmap,
+               TextureSlot::Specular,
+            ];
          }
-         _ => (),
+         // 34, 35 and anything past 43 have no published definition; the
+         // reference XPS tools leave them undefined as well.
+         _ => known = false,
       };
       RenderGroup {
+         id: if known {
+            RenderGroupId::Known(render_group_num)
+         } else {
+            RenderGroupId::Unknown(render_group_num)
+         },
          alpha: alpha,
          posable: posable,
          specular: specular.to_string(),
          bump1_rep: bump1_rep,
          bump2_rep: bump2_rep,
          spec1_rep: spec1_rep,
-         tex_count: tex_count,
-         texture_types: texture_types
-            .into_iter()
-            .map(|x| {
-               std::ffi::CString::new(x.to_string()).unwrap_or(std::ffi::CString::new("").unwrap())
-            })
-            .collect(),
+         texture_slots,
       }
    }
 }
+
+impl RenderGroup {
+   pub fn texture_count(&self) -> usize {
+      self.texture_slots.len()
+   }
+
+   pub fn texture_slot(&self, index: usize) -> TextureSlot {
+      self
+         .texture_slots
+         .get(index)
+         .cloned()
+         .unwrap_or(TextureSlot::Unknown)
+   }
+}
+
+impl Mesh {
+   pub fn texture(&self, slot: TextureSlot) -> Option<&Texture> {
+      self.textures.iter().find(|x| x.slot == slot)
+   }
+}
+
+pub fn render_group_warnings(meshes: &[Mesh]) -> Vec<XpsWarning> {
+   let mut warnings = vec![];
+   for (index, mesh) in meshes.iter().enumerate() {
+      if let RenderGroupId::Unknown(number) = mesh.render_group.id {
+         warnings.push(XpsWarning::new(
+            WarningKind::UnknownRenderGroup,
+            Some(index),
+            format!(
+               "mesh '{}' uses unknown render group {}",
+               mesh.name.to_string_lossy(),
+               number
+            ),
+         ));
+      }
+   }
+   warnings
+}
+
+pub fn texture_count_warnings(meshes: &[Mesh]) -> Vec<XpsWarning> {
+   let mut warnings = vec![];
+   for (index, mesh) in meshes.iter().enumerate() {
+      let expected = mesh.render_group.texture_count();
+      let known = matches!(mesh.render_group.id, RenderGroupId::Known(_));
+      if known && mesh.textures.len() != expected {
+         warnings.push(XpsWarning::new(
+            WarningKind::TextureCountMismatch,
+            Some(index),
+            format!(
+               "mesh '{}' has {} textures, render group expects {}",
+               mesh.name.to_string_lossy(),
+               mesh.textures.len(),
+               expected
+            ),
+         ));
+      }
+   }
+   warnings
+}
+
+#[cfg(test)]
+mod tests {
+   use super::*;
+   use crate::test_util;
+   use std::ffi::CString;
+
+   fn mesh_with_textures(render_group: i32, count: usize) -> Mesh {
+      let mut mesh = test_util::mesh(vec![], vec![]);
+      mesh.render_group = RenderGroup::new(render_group);
+      mesh.textures = (0..count)
+         .map(|index| Texture {
+            id: index as u16,
+            file: CString::new(format!("{}.dds", index)).unwrap(),
+            path: CString::new(format!("{}.dds", index)).unwrap(),
+            uv_layer: 0,
+            slot: TextureSlot::Unknown,
+         })
+         .collect();
+      mesh
+   }
+
+   #[test]
+   fn texture_count_mismatch_is_reported_per_mesh() {
+      let meshes = vec![
+         mesh_with_textures(40, 3),
+         mesh_with_textures(40, 1),
+         mesh_with_textures(99, 0),
+      ];
+      let warnings = texture_count_warnings(&meshes);
+      assert_eq!(warnings.len(), 1);
+      assert_eq!(warnings[0].kind, WarningKind::TextureCountMismatch);
+      assert_eq!(warnings[0].mesh_index, Some(1));
+      assert_eq!(
+         warnings[0].message.to_str().unwrap(),
+         "mesh 'mesh' has 1 textures, render group expects 3"
+      );
+
+      let warnings = render_group_warnings(&meshes);
+      assert_eq!(warnings.len(), 1);
+      assert_eq!(warnings[0].kind, WarningKind::UnknownRenderGroup);
+      assert_eq!(warnings[0].mesh_index, Some(2));
+   }
+}
@@ -0,0 +1,170 @@
+pub type Vec3 = [f32; 3];
+pub type Quat = [f32; 4];
+
+pub fn add(a: Vec3, b: Vec3) -> Vec3 {
+  [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+pub fn sub(a: Vec3, b: Vec3) -> Vec3 {
+  [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub fn scale(a: Vec3, s: f32) -> Vec3 {
+  [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn dot(a: Vec3, b: Vec3) -> f32 {
+  a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: Vec3, b: Vec3) -> Vec3 {
+  [
+    a[1] * b[2] - a[2] * b[1],
+    a[2] * b[0] - a[0] * b[2],
+    a[0] * b[1] - a[1] * b[0],
+  ]
+}
+
+pub fn length(a: Vec3) -> f32 {
+  dot(a, a).sqrt()
+}
+
+pub fn normalize(a: Vec3) -> Vec3 {
+  let len = length(a);
+  if len > 0_f32 {
+    scale(a, 1_f32 / len)
+  } else {
+    a
+  }
+}
+
+pub const QUAT_IDENTITY: Quat = [0_f32, 0_f32, 0_f32, 1_f32];
+
+pub fn quat_from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+  let half = angle * 0.5;
+  let s = half.sin();
+  [axis[0] * s, axis[1] * s, axis[2] * s, half.cos()]
+}
+
+pub fn quat_mul(a: Quat, b: Quat) -> Quat {
+  [
+    a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+    a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+    a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+    a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+  ]
+}
+
+// XPS pose rotations are degrees applied in Y, X, Z order.
+pub fn quat_from_xps_euler(degrees: Vec3) -> Quat {
+  let x = quat_from_axis_angle([1_f32, 0_f32, 0_f32], degrees[0].to_radians());
+  let y = quat_from_axis_angle([0_f32, 1_f32, 0_f32], degrees[1].to_radians());
+  let z = quat_from_axis_angle([0_f32, 0_f32, 1_f32], degrees[2].to_radians());
+  quat_mul(z, quat_mul(x, y))
+}
+
+// Radians applied in X, Y, Z order, as used by SMD and most engines.
+pub fn quat_to_euler_xyz(q: Quat) -> Vec3 {
+  let [x, y, z, w] = q;
+  let sin_y = (2_f32 * (w * y - z * x)).clamp(-1_f32, 1_f32);
+  [
+    (2_f32 * (w * x + y * z)).atan2(1_f32 - 2_f32 * (x * x + y * y)),
+    sin_y.asin(),
+    (2_f32 * (w * z + x * y)).atan2(1_f32 - 2_f32 * (y * y + z * z)),
+  ]
+}
+
+pub fn quat_conjugate(q: Quat) -> Quat {
+  [-q[0], -q[1], -q[2], q[3]]
+}
+
+pub fn quat_rotate(q: Quat, v: Vec3) -> Vec3 {
+  let u = [q[0], q[1], q[2]];
+  let t = scale(cross(u, v), 2_f32);
+  add(add(v, scale(t, q[3])), cross(u, t))
+}
+
+// Column-major: `m[column][row]`, translation in `m[3]`.
+pub type Mat4 = [[f32; 4]; 4];
+
+pub const MAT4_IDENTITY: Mat4 = [
+  [1_f32, 0_f32, 0_f32, 0_f32],
+  [0_f32, 1_f32, 0_f32, 0_f32],
+  [0_f32, 0_f32, 1_f32, 0_f32],
+  [0_f32, 0_f32, 0_f32, 1_f32],
+];
+
+pub fn mat4_from_rotation_translation(q: Quat, t: Vec3) -> Mat4 {
+  let x = quat_rotate(q, [1_f32, 0_f32, 0_f32]);
+  let y = quat_rotate(q, [0_f32, 1_f32, 0_f32]);
+  let z = quat_rotate(q, [0_f32, 0_f32, 1_f32]);
+  [
+    [x[0], x[1], x[2], 0_f32],
+    [y[0], y[1], y[2], 0_f32],
+    [z[0], z[1], z[2], 0_f32],
+    [t[0], t[1], t[2], 1_f32],
+  ]
+}
+
+pub fn mat4_mul(a: &Mat4, b: &Mat4) -> Mat4 {
+  let mut out = [[0_f32; 4]; 4];
+  for (column, out_column) in out.iter_mut().enumerate() {
+    for (row, value) in out_column.iter_mut().enumerate() {
+      *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+    }
+  }
+  out
+}
+
+// Inverse of a rotation + translation matrix.
+pub fn mat4_inverse_rigid(m: &Mat4) -> Mat4 {
+  let mut out = MAT4_IDENTITY;
+  for column in 0..3 {
+    for row in 0..3 {
+      out[column][row] = m[row][column];
+    }
+  }
+  let t = [m[3][0], m[3][1], m[3][2]];
+  out[3] = [
+    -dot([m[0][0], m[0][1], m[0][2]], t),
+    -dot([m[1][0], m[1][1], m[1][2]], t),
+    -dot([m[2][0], m[2][1], m[2][2]], t),
+    1_f32,
+  ];
+  out
+}
+
+// Orthonormal basis vectors as the columns of a rotation matrix.
+pub fn quat_from_basis(x: Vec3, y: Vec3, z: Vec3) -> Quat {
+  let trace = x[0] + y[1] + z[2];
+  let q = if trace > 0_f32 {
+    let s = (trace + 1_f32).sqrt() * 2_f32;
+    [(y[2] - z[1]) / s, (z[0] - x[2]) / s, (x[1] - y[0]) / s, 0.25 * s]
+  } else if x[0] > y[1] && x[0] > z[2] {
+    let s = (1_f32 + x[0] - y[1] - z[2]).sqrt() * 2_f32;
+    [0.25 * s, (y[0] + x[1]) / s, (z[0] + x[2]) / s, (y[2] - z[1]) / s]
+  } else if y[1] > z[2] {
+    let s = (1_f32 + y[1] - x[0] - z[2]).sqrt() * 2_f32;
+    [(y[0] + x[1]) / s, 0.25 * s, (z[1] + y[2]) / s, (z[0] - x[2]) / s]
+  } else {
+    let s = (1_f32 + z[2] - x[0] - y[1]).sqrt() * 2_f32;
+    [(z[0] + x[2]) / s, (z[1] + y[2]) / s, 0.25 * s, (x[1] - y[0]) / s]
+  };
+  let len = (q[0] * q[0] + q[1] * q[1] + q[2] * q[2] + q[3] * q[3]).sqrt();
+  [q[0] / len, q[1] / len, q[2] / len, q[3] / len]
+}
+
+// Inverse of `quat_from_xps_euler`, in degrees.
+pub fn quat_to_xps_euler(q: Quat) -> Vec3 {
+  let [x, y, z, w] = q;
+  let m21 = (2_f32 * (y * z + w * x)).clamp(-1_f32, 1_f32);
+  let m20 = 2_f32 * (x * z - w * y);
+  let m22 = 1_f32 - 2_f32 * (x * x + y * y);
+  let m01 = 2_f32 * (x * y - w * z);
+  let m11 = 1_f32 - 2_f32 * (x * x + z * z);
+  [
+    m21.asin().to_degrees(),
+    (-m20).atan2(m22).to_degrees(),
+    (-m01).atan2(m11).to_degrees(),
+  ]
+}
@@ -0,0 +1,381 @@
+use super::types::{BoneWeight, Mesh, Vertex};
+use std::borrow::Cow;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum IndexBuffer {
+  U16(Vec<u16>),
+  U32(Vec<u32>),
+}
+
+impl Default for IndexBuffer {
+  fn default() -> IndexBuffer {
+    IndexBuffer::U16(vec![])
+  }
+}
+
+impl IndexBuffer {
+  // 16-bit whenever every index fits.
+  pub fn new(faces: &[u32]) -> IndexBuffer {
+    if faces.iter().all(|x| *x <= u16::MAX as u32) {
+      IndexBuffer::U16(faces.iter().map(|x| *x as u16).collect())
+    } else {
+      IndexBuffer::U32(faces.to_vec())
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    match self {
+      IndexBuffer::U16(x) => x.len(),
+      IndexBuffer::U32(x) => x.len(),
+    }
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  pub fn index_size(&self) -> usize {
+    match self {
+      IndexBuffer::U16(_) => 2,
+      IndexBuffer::U32(_) => 4,
+    }
+  }
+}
+
+// Vertex attributes with one contiguous array per attribute and one UV
+// stream per layer. Joints and weights hold `influences` entries per
+// vertex; vertices with fewer influences are padded with bone 0 at zero
+// weight. `indices` is a copy of `Mesh::faces`, which stays the one the
+// crate reads and edits; packing and `update_index_buffer` refresh it.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MeshBuffers {
+  pub positions: Vec<[f32; 3]>,
+  pub normals: Vec<[f32; 3]>,
+  pub colors: Vec<[u8; 4]>,
+  pub uvs: Vec<Vec<[f32; 2]>>,
+  pub influences: usize,
+  pub joints: Vec<i16>,
+  pub weights: Vec<f32>,
+  pub indices: IndexBuffer,
+}
+
+impl MeshBuffers {
+  pub fn new(uv_count: usize) -> MeshBuffers {
+    MeshBuffers {
+      uvs: vec![vec![]; uv_count],
+      ..MeshBuffers::default()
+    }
+  }
+
+  pub fn from_vertices(vertices: &[Vertex], uv_count: usize, faces: &[u32]) -> MeshBuffers {
+    let mut buffers = MeshBuffers::new(uv_count);
+    for vertex in vertices {
+      buffers.push_vertex(
+        vertex.position,
+        vertex.normal,
+        vertex.color,
+        &vertex.uv,
+        &vertex.bone_weights,
+      );
+    }
+    buffers.indices = IndexBuffer::new(faces);
+    buffers
+  }
+
+  pub fn vertex_count(&self) -> usize {
+    self.positions.len()
+  }
+
+  fn widen(&mut self, influences: usize) {
+    let mut joints = Vec::with_capacity(self.vertex_count() * influences);
+    let mut weights = Vec::with_capacity(self.vertex_count() * influences);
+    for index in 0..self.vertex_count() {
+      let range = index * self.influences..(index + 1) * self.influences;
+      joints.extend_from_slice(&self.joints[range.clone()]);
+      weights.extend_from_slice(&self.weights[range]);
+      joints.resize((index + 1) * influences, 0);
+      weights.resize((index + 1) * influences, 0_f32);
+    }
+    self.joints = joints;
+    self.weights = weights;
+    self.influences = influences;
+  }
+
+  pub fn push_vertex(
+    &mut self,
+    position: [f32; 3],
+    normal: [f32; 3],
+    color: [u8; 4],
+    uv: &[[f32; 2]],
+    bone_weights: &[BoneWeight],
+  ) {
+    if bone_weights.len() > self.influences {
+      self.widen(bone_weights.len());
+    }
+    self.positions.push(position);
+    self.normals.push(normal);
+    self.colors.push(color);
+    for (layer, stream) in self.uvs.iter_mut().enumerate() {
+      stream.push(uv.get(layer).cloned().unwrap_or_default());
+    }
+    let end = self.joints.len() + self.influences;
+    self.joints.extend(bone_weights.iter().map(|x| x.id));
+    self.weights.extend(bone_weights.iter().map(|x| x.weight));
+    self.joints.resize(end, 0);
+    self.weights.resize(end, 0_f32);
+  }
+
+  pub fn bone_weights(&self, index: usize) -> Vec<BoneWeight> {
+    let range = index * self.influences..(index + 1) * self.influences;
+    self.joints[range.clone()]
+      .iter()
+      .zip(self.weights[range].iter())
+      .map(|(id, weight)| BoneWeight {
+        id: *id,
+        weight: *weight,
+      })
+      .collect()
+  }
+
+  // Writes up to `influences` entries; the rest of the vertex's slots are
+  // cleared.
+  pub fn set_bone_weights(&mut self, index: usize, bone_weights: &[BoneWeight]) {
+    for slot in 0..self.influences {
+      let bone_weight = bone_weights.get(slot).cloned().unwrap_or_default();
+      self.joints[index * self.influences + slot] = bone_weight.id;
+      self.weights[index * self.influences + slot] = bone_weight.weight;
+    }
+  }
+
+  pub fn vertex(&self, index: usize) -> Vertex {
+    Vertex {
+      position: self.positions[index],
+      normal: self.normals[index],
+      color: self.colors[index],
+      uv: self.uvs.iter().map(|x| x[index]).collect(),
+      bone_weights: self.bone_weights(index),
+      merged: false,
+    }
+  }
+
+  pub fn to_vertices(&self) -> Vec<Vertex> {
+    (0..self.vertex_count()).map(|x| self.vertex(x)).collect()
+  }
+
+  // `order[new] = old`.
+  pub fn permute(&mut self, order: &[usize]) {
+    self.positions = order.iter().map(|x| self.positions[*x]).collect();
+    self.normals = order.iter().map(|x| self.normals[*x]).collect();
+    self.colors = order.iter().map(|x| self.colors[*x]).collect();
+    for stream in self.uvs.iter_mut() {
+      *stream = order.iter().map(|x| stream[*x]).collect();
+    }
+    let influences = self.influences;
+    self.joints = order
+      .iter()
+      .flat_map(|x| {
+        self.joints[x * influences..(x + 1) * influences]
+          .iter()
+          .cloned()
+      })
+      .collect();
+    self.weights = order
+      .iter()
+      .flat_map(|x| {
+        self.weights[x * influences..(x + 1) * influences]
+          .iter()
+          .cloned()
+      })
+      .collect();
+  }
+}
+
+// Meshes loaded with `ImportParameters::mesh_buffers` keep their vertex data
+// in `buffers` and leave `vertices` empty.
+impl Mesh {
+  pub fn vertex_count(&self) -> usize {
+    match &self.buffers {
+      Some(x) => x.vertex_count(),
+      None => self.vertices.len(),
+    }
+  }
+
+  pub fn vertex_position(&self, index: usize) -> [f32; 3] {
+    match &self.buffers {
+      Some(x) => x.positions[index],
+      None => self.vertices[index].position,
+    }
+  }
+
+  pub fn vertex_positions(&self) -> impl Iterator<Item = &[f32; 3]> + Clone {
+    // One of the two sources is always empty.
+    let buffered = self.buffers.as_ref().map_or(&[][..], |x| &x.positions[..]);
+    buffered
+      .iter()
+      .chain(self.vertices.iter().map(|x| &x.position))
+  }
+
+  pub fn vertex_normal(&self, index: usize) -> [f32; 3] {
+    match &self.buffers {
+      Some(x) => x.normals[index],
+      None => self.vertices[index].normal,
+    }
+  }
+
+  pub fn vertex_color(&self, index: usize) -> [u8; 4] {
+    match &self.buffers {
+      Some(x) => x.colors[index],
+      None => self.vertices[index].color,
+    }
+  }
+
+  pub fn vertex_uv(&self, index: usize, layer: usize) -> Option<[f32; 2]> {
+    match &self.buffers {
+      Some(x) => x.uvs.get(layer).map(|stream| stream[index]),
+      None => self.vertices[index].uv.get(layer).cloned(),
+    }
+  }
+
+  // Buffered meshes report the padded number of slots.
+  pub fn vertex_bone_weight_count(&self, index: usize) -> usize {
+    match &self.buffers {
+      Some(x) => x.influences,
+      None => self.vertices[index].bone_weights.len(),
+    }
+  }
+
+  // Bone 0 with zero weight past the vertex's slots.
+  pub fn vertex_bone_weight(&self, index: usize, slot: usize) -> BoneWeight {
+    match &self.buffers {
+      Some(x) if slot < x.influences => BoneWeight {
+        id: x.joints[index * x.influences + slot],
+        weight: x.weights[index * x.influences + slot],
+      },
+      Some(_) => BoneWeight::default(),
+      None => self.vertices[index]
+        .bone_weights
+        .get(slot)
+        .cloned()
+        .unwrap_or_default(),
+    }
+  }
+
+  // The mesh with buffered vertex data moved into `vertices`, for passes
+  // that work on `Vertex`. Unbuffered meshes are borrowed as they are.
+  pub fn unpacked(&self) -> Cow<'_, Mesh> {
+    match &self.buffers {
+      Some(x) => Cow::Owned(Mesh {
+        vertices: x.to_vertices(),
+        buffers: None,
+        ..self.clone()
+      }),
+      None => Cow::Borrowed(self),
+    }
+  }
+
+  pub fn vertex_normals_mut(&mut self) -> Vec<&mut [f32; 3]> {
+    match self.buffers.as_mut() {
+      Some(x) => x.normals.iter_mut().collect(),
+      None => self.vertices.iter_mut().map(|x| &mut x.normal).collect(),
+    }
+  }
+
+  // In-place form of `unpacked`; returns whether the mesh was buffered.
+  pub fn unpack_buffers(&mut self) -> bool {
+    match self.buffers.take() {
+      Some(x) => {
+        self.vertices = x.to_vertices();
+        true
+      }
+      None => false,
+    }
+  }
+
+  // Moves `vertices` back into `buffers`.
+  pub fn pack_buffers(&mut self) {
+    self.buffers = Some(self.to_buffers());
+    self.vertices = vec![];
+  }
+
+  // `order[new] = old`; faces are not touched.
+  pub fn permute_vertices(&mut self, order: &[usize]) {
+    match self.buffers.as_mut() {
+      Some(x) => x.permute(order),
+      None => self.vertices = order.iter().map(|x| self.vertices[*x].clone()).collect(),
+    }
+  }
+
+  // Copies `faces` into the index buffer after passes that edited them.
+  pub fn update_index_buffer(&mut self) {
+    if let Some(x) = self.buffers.as_mut() {
+      x.indices = IndexBuffer::new(&self.faces);
+    }
+  }
+
+  pub fn to_buffers(&self) -> MeshBuffers {
+    match &self.buffers {
+      Some(x) => x.clone(),
+      None => MeshBuffers::from_vertices(&self.vertices, self.uv_count as usize, &self.faces),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::batching;
+  use crate::test_util;
+  use crate::types::{Bone, Data};
+  use std::ffi::CString;
+
+  fn buffered_cube() -> Mesh {
+    let mut mesh = test_util::cube(false);
+    mesh.pack_buffers();
+    mesh
+  }
+
+  #[test]
+  fn accessors_match_vertices() {
+    let mesh = test_util::cube(false);
+    let buffered = buffered_cube();
+    assert!(buffered.vertices.is_empty());
+    assert_eq!(buffered.vertex_count(), mesh.vertex_count());
+    for index in 0..mesh.vertex_count() {
+      assert_eq!(buffered.vertex_position(index), mesh.vertex_position(index));
+      assert_eq!(buffered.vertex_normal(index), mesh.vertex_normal(index));
+      assert_eq!(buffered.vertex_uv(index, 0), mesh.vertex_uv(index, 0));
+      assert_eq!(buffered.vertex_bone_weight(index, 0).weight, 1_f32);
+    }
+    assert_eq!(buffered.vertex_uv(0, 1), None);
+    assert_eq!(buffered.max_influences(), 1);
+  }
+
+  #[test]
+  fn bounds_cover_buffered_meshes() {
+    let data = Data {
+      bones: vec![Bone {
+        id: 0,
+        name: CString::new("root").unwrap(),
+        co: [0_f32; 3],
+        parent_id: -1,
+        rest_rotation: crate::math::QUAT_IDENTITY,
+      }],
+      meshes: vec![buffered_cube()],
+      ..Data::default()
+    };
+    let aabb = data.aabb().unwrap();
+    assert_eq!(aabb.size(), [1_f32; 3]);
+    assert_eq!(data.bone_aabb(0, 0_f32).unwrap().size(), [1_f32; 3]);
+    assert!(data.bone_bounds(0_f32)[0].is_some());
+  }
+
+  #[test]
+  fn merging_buffered_meshes_offsets_faces() {
+    let merged = batching::merge_meshes(&[buffered_cube(), buffered_cube()]);
+    assert_eq!(merged.len(), 1);
+    let buffers = merged[0].buffers.as_ref().unwrap();
+    assert_eq!(buffers.vertex_count(), 48);
+    assert_eq!(buffers.indices, IndexBuffer::new(&merged[0].faces));
+    assert_eq!(merged[0].faces.iter().max(), Some(&47));
+  }
+}
@@ -1,3 +1,6 @@
+use super::error_types::{WarningKind, XpsWarning};
+use super::types::{Mesh, RenderParameters};
+use std::fmt;
 use std::string::String;
 
 #[derive(Default)]
@@ -24,6 +27,7 @@ pub struct MeshNameParser {
   pub has_bump2_uv_scale: bool,
   pub has_optional_items: bool,
   pub is_visible_by_default: bool,
+  pub warnings: Vec<NameWarning>,
 }
 
 impl MeshNameParser {
@@ -38,55 +42,45 @@ impl MeshNameParser {
     }
     mpn.full_name = mesh_part_name.clone();
     mpn.tokens = mesh_part_name.split("_").map(|x| x.to_string()).collect();
-    if mpn.tokens.len() < 2 {
-      mpn.has_render_group = false;
-      mpn.mesh_name = mesh_part_name.clone();
-      mpn.item_name = mesh_part_name.clone();
-      mpn.item_mesh_part = mesh_part_name;
-      mpn.is_visible_by_default = false;
-      mpn.render_group_number = String::from("0");
+    let (name, warnings) = MeshName::parse_with_warnings(&mesh_part_name);
+    mpn.warnings = warnings;
+
+    mpn.has_render_group = name.render_group.is_some();
+    mpn.mesh_name = name.mesh_part();
+    mpn.item_name = name.item_name.clone();
+    mpn.item_mesh_part = mpn.mesh_name.clone();
+    if let Some(x) = name.render_group {
+      mpn.render_group_number = x.to_string();
+      mpn.is_visible_by_default = x != 0;
     } else {
-      mpn.has_render_group = true;
-      mpn.item_mesh_part = mpn.tokens[1].clone();
-      mpn.mesh_name = mpn.tokens[1].clone();
-      mpn.item_name = mpn.tokens[1].clone();
-      mpn.is_visible_by_default = !(mpn.tokens[0] == "0");
-      mpn.render_group_number = mpn.tokens[0].clone();
-    }
-    if mpn.tokens.len() > 2 {
-      mpn.has_bump2_uv_scale = true;
-      mpn.has_specular_amount = true;
-      mpn.has_bump1_uv_scale = true;
-
-      mpn.specular_amount = mpn.get_param(2);
-      mpn.bump1_uv_scale = mpn.get_param(3);
-      mpn.bump2_uv_scale = mpn.get_param(4);
-    }
-    if mpn.tokens.len() > 5 {
-      mpn.get_camera();
-    }
-    if mpn.mesh_name.starts_with("+") || mpn.mesh_name.starts_with("-") {
+      mpn.is_visible_by_default = false;
+    }
+
+    mpn.has_specular_amount = name.specular_amount.is_some();
+    mpn.has_bump1_uv_scale = name.bump1_uv_scale.is_some();
+    mpn.has_bump2_uv_scale = name.bump2_uv_scale.is_some();
+    let parameters = name.render_parameters();
+    mpn.specular_amount = parameters.specular_amount;
+    mpn.bump1_uv_scale = parameters.bump1_uv_scale;
+    mpn.bump2_uv_scale = parameters.bump2_uv_scale;
+
+    if let Some(x) = &name.camera {
+      mpn.has_camera_targets = true;
+      mpn.camera_name = x.clone();
+      mpn.camera_targets = if name.camera_targets.is_empty() {
+        vec![String::from("root")]
+      } else {
+        name.camera_targets.clone()
+      };
+    }
+
+    if let Some(visible) = name.visible_by_default {
       mpn.has_optional_items = true;
-      mpn.is_visible_by_default = mpn.mesh_name.starts_with("+");
-      let string_array: Vec<String> = mpn.mesh_name.split(".").map(|x| x.to_string()).collect();
-      match string_array.len() {
-        0 => {
-          mpn.item_mesh_part = String::from(&mpn.mesh_name[..1]); // mpn.meshName.substring(1);
-          mpn.item_name = mpn.item_mesh_part.clone();
-        }
-        1 => {
-          mpn.item_mesh_part = String::from(&string_array[0][..1]);
-          mpn.item_name = mpn.item_mesh_part.clone();
-        }
-        2 => {
-          mpn.item_name = String::from(&string_array[0][..1]);
-          mpn.item_mesh_part = string_array[1].clone();
-        }
-        _ => {
-          mpn.item_name = String::from(&string_array[0][..1]);
-          mpn.item_mesh_part = String::from(&mpn.mesh_name[..string_array[0].len()]);
-        }
-      }
+      mpn.is_visible_by_default = visible;
+      mpn.item_mesh_part = name
+        .item_mesh_part
+        .clone()
+        .unwrap_or_else(|| name.item_name.clone());
     }
     mpn.normalize_full_name();
 
@@ -156,58 +150,367 @@ impl MeshNameParser {
     self.render_group_number = String::from("0");
   }
 
-  fn get_camera(&mut self) {
-    if self.tokens.len() <= 5 {
-      return;
-    }
-    self.has_camera_targets = true;
-    self.camera_name = self.tokens[5].clone();
-    if self.tokens.len() == 6 {
-      self.camera_targets = vec![String::from("root")];
+  pub fn get_full_name(&self) -> String {
+    self.full_name_normalized.clone()
+  }
+
+  pub fn get_render_group_number(&self) -> i32 {
+    if let Ok(x) = self.render_group_number.parse() {
+      x
     } else {
-      self.camera_targets = vec![String::new(); self.tokens.len() - 6];
-      for index in 0..self.tokens.len() - 6 {
-        self.camera_targets[index] = self.tokens[index + 6].clone();
+      0
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum NameWarning {
+  MissingRenderGroup,
+  EmptyItemName,
+  MissingParameters { found: usize },
+  InvalidParameter { index: usize, text: String },
+}
+
+impl fmt::Display for NameWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      NameWarning::MissingRenderGroup => write!(f, "no render group"),
+      NameWarning::EmptyItemName => write!(f, "empty item name"),
+      NameWarning::MissingParameters { found } => {
+        write!(f, "expected 3 render parameters, found {}", found)
+      }
+      NameWarning::InvalidParameter { index, text } => {
+        write!(f, "render parameter {} is not a number: '{}'", index + 1, text)
       }
     }
   }
+}
 
-  fn get_param(&mut self, index: i32) -> f32 {
-    if let Ok(v) = self.tokens[index as usize].parse() {
-      return v;
-    } else {
-      match index {
-        2 => {
-          self.has_specular_amount = false;
-          self.specular_amount = 0.1;
-          return 0.1;
-        }
-        3 => {
-          self.has_bump1_uv_scale = false;
-          self.bump1_uv_scale = 1_f32;
-          return 0.0;
-        }
-        4 => {
-          self.has_bump2_uv_scale = false;
-          self.bump2_uv_scale = 1_f32;
-          return 0.0;
-        }
-        _ => {
-          return 0.0;
-        }
+// Structured form of an encoded mesh name:
+// `group_[+|-]item[.part]_specular_bump1_bump2_camera_target_target...`.
+// The original text of each number is kept, so `parse` followed by
+// `to_string` gives back the same name unless a value was changed.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct MeshName {
+  pub render_group: Option<i32>,
+  pub visible_by_default: Option<bool>,
+  pub item_name: String,
+  pub item_mesh_part: Option<String>,
+  pub specular_amount: Option<f32>,
+  pub bump1_uv_scale: Option<f32>,
+  pub bump2_uv_scale: Option<f32>,
+  pub camera: Option<String>,
+  pub camera_targets: Vec<String>,
+  source: [Option<String>; 4],
+}
+
+fn number_text<T: std::str::FromStr + PartialEq + ToString>(
+  source: &Option<String>,
+  value: Option<T>,
+) -> Option<String> {
+  match source {
+    Some(x) if x.parse::<T>().ok() == value => Some(x.clone()),
+    _ => value.map(|x| x.to_string()),
+  }
+}
+
+impl MeshName {
+  pub fn new(render_group: i32, item_name: &str) -> MeshName {
+    MeshName {
+      render_group: Some(render_group),
+      item_name: item_name.to_string(),
+      ..MeshName::default()
+    }
+  }
+
+  pub fn optional(mut self, visible_by_default: bool) -> MeshName {
+    self.visible_by_default = Some(visible_by_default);
+    self
+  }
+
+  pub fn part(mut self, item_mesh_part: &str) -> MeshName {
+    self.item_mesh_part = Some(item_mesh_part.to_string());
+    self
+  }
+
+  pub fn specular_amount(mut self, value: f32) -> MeshName {
+    self.specular_amount = Some(value);
+    self
+  }
+
+  pub fn bump_uv_scales(mut self, bump1: f32, bump2: f32) -> MeshName {
+    self.bump1_uv_scale = Some(bump1);
+    self.bump2_uv_scale = Some(bump2);
+    self
+  }
+
+  pub fn camera(mut self, name: &str, targets: &[&str]) -> MeshName {
+    self.camera = Some(name.to_string());
+    self.camera_targets = targets.iter().map(|x| x.to_string()).collect();
+    self
+  }
+
+  pub fn parse(name: &str) -> MeshName {
+    MeshName::parse_with_warnings(name).0
+  }
+
+  // The first run of three numbers after the item name holds the
+  // parameters, so item names may contain underscores and camera targets
+  // may be numeric. Without such a run, the three tokens after the item
+  // name are read by position as long as any of them is a number, and the
+  // others are reported as invalid; if none is, they are part of the item
+  // name.
+  pub fn parse_with_warnings(name: &str) -> (MeshName, Vec<NameWarning>) {
+    let mut mesh_name = MeshName::default();
+    let mut warnings = vec![];
+    let tokens: Vec<&str> = name.split('_').collect();
+    let render_group = tokens[0].parse::<i32>().ok().filter(|_| tokens.len() > 1);
+    if render_group.is_none() {
+      warnings.push(NameWarning::MissingRenderGroup);
+      mesh_name.set_mesh_part(name);
+      return (mesh_name, warnings);
+    }
+    mesh_name.render_group = render_group;
+    mesh_name.source[0] = Some(tokens[0].to_string());
+
+    let rest = &tokens[1..];
+    let is_number = |x: &&str| x.parse::<f32>().is_ok();
+    let is_triple = |x: usize| rest[x..x + 3].iter().all(is_number);
+    let triple = (1..rest.len().saturating_sub(2)).find(|x| is_triple(*x));
+    let (name_end, parameter_count) = match triple {
+      Some(x) => (x, 3),
+      None if rest.len() >= 4 && rest[1..4].iter().any(is_number) => (1, 3),
+      None => {
+        let numbers = rest
+          .iter()
+          .skip(1)
+          .rev()
+          .take_while(|x| is_number(x))
+          .count();
+        (rest.len() - numbers, numbers)
+      }
+    };
+    if parameter_count < 3 && parameter_count > 0 {
+      warnings.push(NameWarning::MissingParameters {
+        found: parameter_count,
+      });
+    }
+
+    let part = rest[..name_end].join("_");
+    if part.is_empty() {
+      warnings.push(NameWarning::EmptyItemName);
+    }
+    mesh_name.set_mesh_part(&part);
+
+    let mut parameters = [None; 3];
+    for (index, value) in parameters.iter_mut().enumerate().take(parameter_count) {
+      let text = rest[name_end + index];
+      *value = text.parse().ok();
+      if value.is_none() {
+        warnings.push(NameWarning::InvalidParameter {
+          index,
+          text: text.to_string(),
+        });
       }
+      mesh_name.source[index + 1] = Some(text.to_string());
     }
+    mesh_name.specular_amount = parameters[0];
+    mesh_name.bump1_uv_scale = parameters[1];
+    mesh_name.bump2_uv_scale = parameters[2];
+
+    let camera_start = name_end + parameter_count;
+    if let Some(x) = rest.get(camera_start) {
+      mesh_name.camera = Some(x.to_string());
+      mesh_name.camera_targets = rest[camera_start + 1..]
+        .iter()
+        .map(|x| x.to_string())
+        .collect();
+    }
+    (mesh_name, warnings)
   }
 
-  pub fn get_full_name(&self) -> String {
-    self.full_name_normalized.clone()
+  fn set_mesh_part(&mut self, text: &str) {
+    let visibility = match text.chars().next() {
+      Some('+') => Some(true),
+      Some('-') => Some(false),
+      _ => None,
+    };
+    if visibility.is_none() {
+      self.item_name = text.to_string();
+      return;
+    }
+    self.visible_by_default = visibility;
+    let text = &text[1..];
+    match text.find('.') {
+      Some(x) => {
+        self.item_name = text[..x].to_string();
+        self.item_mesh_part = Some(text[x + 1..].to_string());
+      }
+      None => self.item_name = text.to_string(),
+    }
   }
 
-  pub fn get_render_group_number(&self) -> i32 {
-    if let Ok(x) = self.render_group_number.parse() {
-      x
+  // The name segment without render group or parameters, e.g. `+hat.brim`.
+  pub fn mesh_part(&self) -> String {
+    let mut out = match self.visible_by_default {
+      Some(true) => String::from("+"),
+      Some(false) => String::from("-"),
+      None => String::new(),
+    };
+    out.push_str(&self.item_name);
+    if let Some(x) = &self.item_mesh_part {
+      out.push('.');
+      out.push_str(x);
+    }
+    out
+  }
+
+  pub fn render_parameters(&self) -> RenderParameters {
+    let default = RenderParameters::default();
+    RenderParameters {
+      specular_amount: self.specular_amount.unwrap_or(default.specular_amount),
+      bump1_uv_scale: self.bump1_uv_scale.unwrap_or(default.bump1_uv_scale),
+      bump2_uv_scale: self.bump2_uv_scale.unwrap_or(default.bump2_uv_scale),
+    }
+  }
+}
+
+impl fmt::Display for MeshName {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut tokens = vec![];
+    let default = RenderParameters::default();
+    let parameters = [
+      (self.specular_amount, default.specular_amount),
+      (self.bump1_uv_scale, default.bump1_uv_scale),
+      (self.bump2_uv_scale, default.bump2_uv_scale),
+    ];
+    // A parameter or camera further along forces the earlier parameters to
+    // be written, using the defaults where they are missing.
+    let mut texts: Vec<Option<String>> = parameters
+      .iter()
+      .enumerate()
+      .map(|(i, x)| number_text(&self.source[i + 1], x.0))
+      .collect();
+    let required = if self.camera.is_some() {
+      3
     } else {
-      0
+      texts
+        .iter()
+        .rposition(|x| x.is_some())
+        .map(|x| x + 1)
+        .unwrap_or(0)
+    };
+    texts.truncate(required);
+    if self.render_group.is_some() || required > 0 {
+      let group = number_text(&self.source[0], self.render_group);
+      tokens.push(group.unwrap_or_else(|| "0".to_string()));
+    }
+    tokens.push(self.mesh_part());
+    for (text, parameter) in texts.into_iter().zip(parameters.iter()) {
+      tokens.push(text.unwrap_or_else(|| parameter.1.to_string()));
+    }
+    if let Some(x) = &self.camera {
+      tokens.push(x.clone());
+      tokens.extend(self.camera_targets.iter().cloned());
+    }
+    write!(f, "{}", tokens.join("_"))
+  }
+}
+
+pub fn mesh_name_warnings(meshes: &[Mesh]) -> Vec<XpsWarning> {
+  let mut warnings = vec![];
+  for (index, mesh) in meshes.iter().enumerate() {
+    let full_name = mesh.full_name.to_string_lossy();
+    for warning in MeshName::parse_with_warnings(&full_name).1 {
+      warnings.push(XpsWarning::new(
+        WarningKind::InvalidMeshName,
+        Some(index),
+        format!("mesh name '{}': {}", full_name, warning),
+      ));
     }
   }
+  warnings
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn underscores_stay_in_item_name() {
+    let (name, warnings) = MeshName::parse_with_warnings("5_hair_front_left_side");
+    assert_eq!(name.item_name, "hair_front_left_side");
+    assert_eq!(name.specular_amount, None);
+    assert!(warnings.is_empty());
+  }
+
+  #[test]
+  fn numeric_camera_targets_are_not_parameters() {
+    let (name, warnings) = MeshName::parse_with_warnings("22_body_0.3_4_8_head_1_2_3");
+    assert_eq!(name.item_name, "body");
+    assert_eq!(name.specular_amount, Some(0.3));
+    assert_eq!(name.bump1_uv_scale, Some(4_f32));
+    assert_eq!(name.bump2_uv_scale, Some(8_f32));
+    assert_eq!(name.camera.as_deref(), Some("head"));
+    assert_eq!(name.camera_targets, vec!["1", "2", "3"]);
+    assert!(warnings.is_empty());
+  }
+
+  #[test]
+  fn underscore_item_with_parameters_and_camera() {
+    let name = MeshName::parse("22_-hat_brim_0.3_4_8_camera_head");
+    assert_eq!(name.item_name, "hat_brim");
+    assert_eq!(name.visible_by_default, Some(false));
+    assert_eq!(name.specular_amount, Some(0.3));
+    assert_eq!(name.camera.as_deref(), Some("camera"));
+    assert_eq!(name.camera_targets, vec!["head"]);
+  }
+
+  #[test]
+  fn malformed_parameters_are_reported() {
+    let (name, warnings) = MeshName::parse_with_warnings("5_body_0.3_x_1");
+    assert_eq!(name.item_name, "body");
+    assert_eq!(name.specular_amount, Some(0.3));
+    assert_eq!(name.bump1_uv_scale, None);
+    assert_eq!(name.bump2_uv_scale, Some(1_f32));
+    assert_eq!(
+      warnings,
+      vec![NameWarning::InvalidParameter {
+        index: 1,
+        text: "x".to_string()
+      }]
+    );
+
+    let (name, warnings) = MeshName::parse_with_warnings("5_body_0.3");
+    assert_eq!(name.item_name, "body");
+    assert_eq!(name.specular_amount, Some(0.3));
+    assert_eq!(warnings, vec![NameWarning::MissingParameters { found: 1 }]);
+
+    let (name, warnings) = MeshName::parse_with_warnings("body_0.3_1_1");
+    assert_eq!(name.render_group, None);
+    assert_eq!(name.item_name, "body_0.3_1_1");
+    assert_eq!(warnings, vec![NameWarning::MissingRenderGroup]);
+
+    let (_, warnings) = MeshName::parse_with_warnings("5__0.3_1_1");
+    assert_eq!(warnings, vec![NameWarning::EmptyItemName]);
+  }
+
+  #[test]
+  fn japanese_names() {
+    let text = "5_+帽子.つば_0.3_1_1_カメラ_頭";
+    let (name, warnings) = MeshName::parse_with_warnings(text);
+    assert!(warnings.is_empty());
+    assert_eq!(name.render_group, Some(5));
+    assert_eq!(name.visible_by_default, Some(true));
+    assert_eq!(name.item_name, "帽子");
+    assert_eq!(name.item_mesh_part.as_deref(), Some("つば"));
+    assert_eq!(name.camera.as_deref(), Some("カメラ"));
+    assert_eq!(name.camera_targets, vec!["頭"]);
+    assert_eq!(name.to_string(), text);
+
+    let parser = MeshNameParser::new(&String::from("髪_前"));
+    assert_eq!(parser.item_name, "髪_前");
+    assert_eq!(parser.warnings, vec![NameWarning::MissingRenderGroup]);
+    assert_eq!(MeshName::parse("7_髪_前").item_name, "髪_前");
+  }
 }
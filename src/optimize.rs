@@ -0,0 +1,264 @@
+use super::math;
+use super::types::{Data, Mesh};
+
+const CACHE_SIZE: i64 = 16;
+const OVERDRAW_CLUSTER: usize = 64;
+
+struct Tipsify {
+  adjacency: Vec<Vec<usize>>,
+  live: Vec<u32>,
+  timestamps: Vec<i64>,
+  emitted: Vec<bool>,
+  dead_end: Vec<u32>,
+  time: i64,
+  cursor: usize,
+}
+
+impl Tipsify {
+  fn new(triangles: &[[u32; 3]], vertex_count: usize) -> Tipsify {
+    let mut adjacency = vec![vec![]; vertex_count];
+    let mut live = vec![0; vertex_count];
+    for (index, triangle) in triangles.iter().enumerate() {
+      for x in triangle {
+        adjacency[*x as usize].push(index);
+        live[*x as usize] += 1;
+      }
+    }
+    Tipsify {
+      adjacency,
+      live,
+      timestamps: vec![0; vertex_count],
+      emitted: vec![false; triangles.len()],
+      dead_end: vec![],
+      time: CACHE_SIZE + 1,
+      cursor: 0,
+    }
+  }
+
+  fn skip_dead_end(&mut self) -> Option<u32> {
+    while let Some(x) = self.dead_end.pop() {
+      if self.live[x as usize] > 0 {
+        return Some(x);
+      }
+    }
+    while self.cursor < self.live.len() {
+      if self.live[self.cursor] > 0 {
+        return Some(self.cursor as u32);
+      }
+      self.cursor += 1;
+    }
+    None
+  }
+
+  fn next_vertex(&mut self, candidates: &[u32]) -> Option<u32> {
+    let mut best = None;
+    let mut best_priority = -1;
+    for x in candidates {
+      let live = self.live[*x as usize] as i64;
+      if live == 0 {
+        continue;
+      }
+      let age = self.time - self.timestamps[*x as usize];
+      let priority = if age + 2 * live <= CACHE_SIZE { age } else { 0 };
+      if priority > best_priority {
+        best_priority = priority;
+        best = Some(*x);
+      }
+    }
+    best.or_else(|| self.skip_dead_end())
+  }
+
+  fn run(mut self, triangles: &[[u32; 3]]) -> Vec<usize> {
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut fanning = self.skip_dead_end();
+    while let Some(vertex) = fanning {
+      let mut candidates = vec![];
+      for triangle in std::mem::take(&mut self.adjacency[vertex as usize]) {
+        if self.emitted[triangle] {
+          continue;
+        }
+        for x in triangles[triangle].iter() {
+          self.dead_end.push(*x);
+          candidates.push(*x);
+          self.live[*x as usize] -= 1;
+          if self.time - self.timestamps[*x as usize] > CACHE_SIZE {
+            self.timestamps[*x as usize] = self.time;
+            self.time += 1;
+          }
+        }
+        self.emitted[triangle] = true;
+        order.push(triangle);
+      }
+      fanning = self.next_vertex(&candidates);
+    }
+    order
+  }
+}
+
+fn triangles_of(mesh: &Mesh) -> Vec<[u32; 3]> {
+  mesh
+    .faces
+    .chunks_exact(3)
+    .filter(|x| x.iter().all(|i| (*i as usize) < mesh.vertex_count()))
+    .map(|x| [x[0], x[1], x[2]])
+    .collect()
+}
+
+fn cluster_sort_key(mesh: &Mesh, cluster: &[[u32; 3]], center: [f32; 3]) -> f32 {
+  let mut centroid = [0_f32; 3];
+  let mut normal = [0_f32; 3];
+  for triangle in cluster {
+    let p: Vec<[f32; 3]> = triangle
+      .iter()
+      .map(|x| mesh.vertex_position(*x as usize))
+      .collect();
+    centroid = math::add(centroid, math::add(p[0], math::add(p[1], p[2])));
+    normal = math::add(normal, math::cross(math::sub(p[1], p[0]), math::sub(p[2], p[0])));
+  }
+  centroid = math::scale(centroid, 1_f32 / (cluster.len() * 3) as f32);
+  let key = math::dot(math::sub(centroid, center), math::normalize(normal));
+  if key.is_finite() {
+    key
+  } else {
+    0_f32
+  }
+}
+
+fn sort_for_overdraw(mesh: &Mesh, triangles: Vec<[u32; 3]>) -> Vec<[u32; 3]> {
+  let center = match mesh.aabb() {
+    Some(x) => x.center(),
+    None => return triangles,
+  };
+  let mut clusters: Vec<(f32, &[[u32; 3]])> = triangles
+    .chunks(OVERDRAW_CLUSTER)
+    .map(|x| (cluster_sort_key(mesh, x, center), x))
+    .collect();
+  clusters.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+  clusters
+    .into_iter()
+    .flat_map(|x| x.1.iter().cloned())
+    .collect()
+}
+
+fn reorder_vertices(mesh: &mut Mesh) {
+  let mut remap = vec![u32::MAX; mesh.vertex_count()];
+  let mut order = Vec::with_capacity(mesh.vertex_count());
+  for index in mesh.faces.iter_mut() {
+    if remap[*index as usize] == u32::MAX {
+      remap[*index as usize] = order.len() as u32;
+      order.push(*index as usize);
+    }
+    *index = remap[*index as usize];
+  }
+  for (vertex, new_index) in remap.iter().enumerate() {
+    if *new_index == u32::MAX {
+      order.push(vertex);
+    }
+  }
+  mesh.permute_vertices(&order);
+}
+
+// Reorders triangles for the post-transform cache (Tipsify) and then
+// vertices by first use. Overdraw sorting moves outward-facing clusters of
+// triangles to the front, trading a little cache efficiency for early-z.
+pub fn optimize_mesh(mesh: &mut Mesh, overdraw: bool) {
+  let triangles = triangles_of(mesh);
+  let order = Tipsify::new(&triangles, mesh.vertex_count()).run(&triangles);
+  let mut sorted: Vec<[u32; 3]> = order.into_iter().map(|x| triangles[x]).collect();
+  if overdraw {
+    sorted = sort_for_overdraw(mesh, sorted);
+  }
+  mesh.faces = sorted.into_iter().flat_map(|x| x.to_vec()).collect();
+  reorder_vertices(mesh);
+}
+
+pub fn optimize_model(data: &mut Data, overdraw: bool) {
+  for mesh in data.meshes.iter_mut() {
+    optimize_mesh(mesh, overdraw);
+  }
+}
+
+pub fn average_cache_miss_ratio(mesh: &Mesh, cache_size: usize) -> f32 {
+  let mut cache: Vec<u32> = Vec::with_capacity(cache_size);
+  let mut misses = 0;
+  for index in &mesh.faces {
+    if !cache.contains(index) {
+      misses += 1;
+      if cache.len() == cache_size {
+        cache.remove(0);
+      }
+      cache.push(*index);
+    }
+  }
+  let triangles = mesh.faces.len() / 3;
+  if triangles == 0 {
+    0_f32
+  } else {
+    misses as f32 / triangles as f32
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  // Grid with its triangles in a scrambled but fixed order.
+  fn scrambled_grid(n: u32) -> Mesh {
+    let mut mesh = test_util::grid(n);
+    let triangles = triangles_of(&mesh);
+    let count = triangles.len();
+    mesh.faces = (0..count)
+      .map(|x| triangles[(x * 7919) % count])
+      .flat_map(|x| x.to_vec())
+      .collect();
+    mesh
+  }
+
+  fn sorted_positions(mesh: &Mesh) -> Vec<[i64; 9]> {
+    let mut triangles: Vec<[i64; 9]> = mesh
+      .faces
+      .chunks_exact(3)
+      .map(|x| {
+        let mut out = [0_i64; 9];
+        for (corner, index) in x.iter().enumerate() {
+          for axis in 0..3 {
+            out[corner * 3 + axis] =
+              (mesh.vertices[*index as usize].position[axis] * 1000_f32).round() as i64;
+          }
+        }
+        out
+      })
+      .collect();
+    triangles.sort();
+    triangles
+  }
+
+  #[test]
+  fn tipsify_order_is_a_permutation() {
+    let mesh = scrambled_grid(20);
+    let triangles = triangles_of(&mesh);
+    let mut order = Tipsify::new(&triangles, mesh.vertices.len()).run(&triangles);
+    order.sort();
+    assert_eq!(order, (0..triangles.len()).collect::<Vec<usize>>());
+  }
+
+  #[test]
+  fn optimize_keeps_triangles_and_lowers_acmr() {
+    let mut mesh = scrambled_grid(20);
+    let before = average_cache_miss_ratio(&mesh, CACHE_SIZE as usize);
+    let triangles = sorted_positions(&mesh);
+    optimize_mesh(&mut mesh, false);
+    let after = average_cache_miss_ratio(&mesh, CACHE_SIZE as usize);
+    assert_eq!(sorted_positions(&mesh), triangles);
+    assert!(after <= before, "{} > {}", after, before);
+  }
+
+  #[test]
+  fn overdraw_sort_keeps_triangles() {
+    let mut mesh = test_util::cube(false);
+    let triangles = sorted_positions(&mesh);
+    optimize_mesh(&mut mesh, true);
+    assert_eq!(sorted_positions(&mesh), triangles);
+  }
+}
@@ -0,0 +1,115 @@
+use super::types::{Data, Mesh};
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct OptionalItem {
+  pub name: String,
+  pub mesh_indices: Vec<usize>,
+  pub visible_by_default: bool,
+}
+
+// Visibility overrides keyed by item name; items not listed keep their
+// default visibility.
+pub type Outfit = HashMap<String, bool>;
+
+impl Mesh {
+  pub fn item_name(&self) -> String {
+    self.item.name.to_string_lossy().to_string()
+  }
+
+  pub fn is_visible_in(&self, outfit: &Outfit) -> bool {
+    if !self.item.optional {
+      return true;
+    }
+    outfit
+      .get(&self.item_name())
+      .cloned()
+      .unwrap_or(self.item.visible_by_default)
+  }
+}
+
+impl Data {
+  // Items in order of first appearance. An item is visible by default if
+  // any of its meshes is. The loader stores the result in
+  // `Data::optional_items`.
+  pub fn collect_optional_items(&self) -> Vec<OptionalItem> {
+    let mut items: Vec<OptionalItem> = vec![];
+    for (index, mesh) in self.meshes.iter().enumerate() {
+      if !mesh.item.optional {
+        continue;
+      }
+      let name = mesh.item_name();
+      match items.iter_mut().find(|x| x.name == name) {
+        Some(x) => {
+          x.mesh_indices.push(index);
+          x.visible_by_default |= mesh.item.visible_by_default;
+        }
+        None => items.push(OptionalItem {
+          name,
+          mesh_indices: vec![index],
+          visible_by_default: mesh.item.visible_by_default,
+        }),
+      }
+    }
+    items
+  }
+
+  // Call after changing the meshes so the cached items match again.
+  pub fn update_optional_items(&mut self) {
+    self.optional_items = self.collect_optional_items();
+  }
+
+  pub fn default_outfit(&self) -> Outfit {
+    self
+      .optional_items
+      .iter()
+      .map(|x| (x.name.clone(), x.visible_by_default))
+      .collect()
+  }
+
+  pub fn visible_meshes(&self, outfit: &Outfit) -> Vec<usize> {
+    (0..self.meshes.len())
+      .filter(|x| self.meshes[*x].is_visible_in(outfit))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::batching;
+  use crate::test_util;
+  use std::ffi::CString;
+
+  fn optional_mesh(item: &str, visible_by_default: bool) -> Mesh {
+    let mut mesh = test_util::cube(false);
+    mesh.item.name = CString::new(item).unwrap();
+    mesh.item.optional = true;
+    mesh.item.visible_by_default = visible_by_default;
+    mesh
+  }
+
+  #[test]
+  fn items_are_cached_and_refreshed_after_merging() {
+    let mut data = Data {
+      meshes: vec![
+        optional_mesh("hat", false),
+        test_util::cube(false),
+        optional_mesh("hat", false),
+        optional_mesh("glasses", false),
+      ],
+      ..Data::default()
+    };
+    data.update_optional_items();
+    assert_eq!(data.optional_items.len(), 2);
+    assert_eq!(data.optional_items[0].name, "hat");
+    assert_eq!(data.optional_items[0].mesh_indices, vec![0, 2]);
+    assert!(!data.optional_items[0].visible_by_default);
+    assert_eq!(data.optional_items[1].mesh_indices, vec![3]);
+
+    batching::merge_model(&mut data);
+    assert_eq!(data.meshes.len(), 3);
+    assert_eq!(data.optional_items[0].mesh_indices, vec![0]);
+    assert_eq!(data.optional_items[1].mesh_indices, vec![2]);
+  }
+}
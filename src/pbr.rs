@@ -0,0 +1,202 @@
+use super::material::TextureSlot;
+use super::types::Mesh;
+
+// Specular intensity used by render groups that have specular but do not
+// read it from the mesh name.
+pub const DEFAULT_SPECULAR_INTENSITY: f32 = 1_f32;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum AlphaMode {
+  Opaque,
+  Blend,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MaterialSlot {
+  BaseColor,
+  Normal,
+  Occlusion,
+  Emission,
+  Specular,
+  DetailMask,
+  DetailNormal1,
+  DetailNormal2,
+  Environment,
+}
+
+impl MaterialSlot {
+  pub const ALL: [MaterialSlot; 9] = [
+    MaterialSlot::BaseColor,
+    MaterialSlot::Normal,
+    MaterialSlot::Occlusion,
+    MaterialSlot::Emission,
+    MaterialSlot::Specular,
+    MaterialSlot::DetailMask,
+    MaterialSlot::DetailNormal1,
+    MaterialSlot::DetailNormal2,
+    MaterialSlot::Environment,
+  ];
+
+  pub fn from_index(index: i32) -> Option<MaterialSlot> {
+    MaterialSlot::ALL.get(index as usize).cloned()
+  }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialTexture {
+  pub texture_index: usize,
+  pub uv_layer: u16,
+  pub tiling: f32,
+}
+
+// Detail normals are blended over the main normal map where the matching
+// channel of the mask (red for bump1, green for bump2) is set.
+#[derive(Clone, PartialEq, Debug)]
+pub struct PbrMaterial {
+  pub base_color: Option<MaterialTexture>,
+  pub base_color_factor: [f32; 4],
+  pub normal: Option<MaterialTexture>,
+  pub occlusion: Option<MaterialTexture>,
+  pub emission: Option<MaterialTexture>,
+  pub emissive_factor: [f32; 3],
+  pub specular: Option<MaterialTexture>,
+  pub specular_intensity: f32,
+  pub detail_mask: Option<MaterialTexture>,
+  pub detail_normal1: Option<MaterialTexture>,
+  pub detail_normal2: Option<MaterialTexture>,
+  pub environment: Option<MaterialTexture>,
+  pub metallic_factor: f32,
+  pub roughness_factor: f32,
+  pub alpha_mode: AlphaMode,
+}
+
+fn find(mesh: &Mesh, slots: &[TextureSlot], tiling: f32) -> Option<MaterialTexture> {
+  let index = mesh.textures.iter().position(|x| slots.contains(&x.slot))?;
+  Some(MaterialTexture {
+    texture_index: index,
+    uv_layer: mesh.textures[index].uv_layer,
+    tiling,
+  })
+}
+
+// XPS specular is Blinn-Phong intensity; stronger highlights map to a
+// smoother surface, from fully rough (no specular) down to 0.5.
+pub fn roughness_from_specular(intensity: f32) -> f32 {
+  1_f32 - 0.5 * intensity.clamp(0_f32, 1_f32)
+}
+
+impl PbrMaterial {
+  pub fn new(mesh: &Mesh) -> PbrMaterial {
+    let group = &mesh.render_group;
+    let parameters = &mesh.render_parameters;
+    let specular_intensity = match group.specular.as_str() {
+      "Yes intensity" => parameters.specular_amount,
+      "Yes" => DEFAULT_SPECULAR_INTENSITY,
+      _ => 0_f32,
+    };
+    let bump1_tiling = if group.bump1_rep {
+      parameters.bump1_uv_scale
+    } else {
+      1_f32
+    };
+    let bump2_tiling = if group.bump2_rep {
+      parameters.bump2_uv_scale
+    } else {
+      1_f32
+    };
+    // Repeating specular maps share the bump1 UV scale.
+    let specular_tiling = if group.spec1_rep {
+      parameters.bump1_uv_scale
+    } else {
+      1_f32
+    };
+    let emission = find(
+      mesh,
+      &[TextureSlot::Emission, TextureSlot::EmissionMiniMap],
+      1_f32,
+    );
+    PbrMaterial {
+      base_color: find(mesh, &[TextureSlot::Diffuse], 1_f32),
+      base_color_factor: [1_f32; 4],
+      normal: find(mesh, &[TextureSlot::Bumpmap], 1_f32),
+      occlusion: find(mesh, &[TextureSlot::Lightmap], 1_f32),
+      emissive_factor: if emission.is_some() {
+        [1_f32; 3]
+      } else {
+        [0_f32; 3]
+      },
+      emission,
+      specular: find(mesh, &[TextureSlot::Specular], specular_tiling),
+      specular_intensity,
+      detail_mask: find(mesh, &[TextureSlot::Mask], 1_f32),
+      detail_normal1: find(mesh, &[TextureSlot::Bump1], bump1_tiling),
+      detail_normal2: find(mesh, &[TextureSlot::Bump2], bump2_tiling),
+      environment: find(mesh, &[TextureSlot::Environment], 1_f32),
+      metallic_factor: 0_f32,
+      roughness_factor: roughness_from_specular(specular_intensity),
+      alpha_mode: if group.alpha {
+        AlphaMode::Blend
+      } else {
+        AlphaMode::Opaque
+      },
+    }
+  }
+
+  pub fn texture(&self, slot: MaterialSlot) -> Option<&MaterialTexture> {
+    match slot {
+      MaterialSlot::BaseColor => self.base_color.as_ref(),
+      MaterialSlot::Normal => self.normal.as_ref(),
+      MaterialSlot::Occlusion => self.occlusion.as_ref(),
+      MaterialSlot::Emission => self.emission.as_ref(),
+      MaterialSlot::Specular => self.specular.as_ref(),
+      MaterialSlot::DetailMask => self.detail_mask.as_ref(),
+      MaterialSlot::DetailNormal1 => self.detail_normal1.as_ref(),
+      MaterialSlot::DetailNormal2 => self.detail_normal2.as_ref(),
+      MaterialSlot::Environment => self.environment.as_ref(),
+    }
+  }
+}
+
+impl Mesh {
+  pub fn pbr_material(&self) -> PbrMaterial {
+    PbrMaterial::new(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::material::RenderGroup;
+  use crate::test_util;
+  use crate::types::Texture;
+  use std::ffi::CString;
+
+  fn textured_mesh(render_group: i32) -> Mesh {
+    let mut mesh = test_util::mesh(vec![], vec![]);
+    mesh.render_group = RenderGroup::new(render_group);
+    mesh.render_parameters.bump1_uv_scale = 4_f32;
+    mesh.textures = mesh
+      .render_group
+      .texture_slots
+      .iter()
+      .enumerate()
+      .map(|(index, slot)| Texture {
+        id: index as u16,
+        file: CString::new(format!("{}.dds", index)).unwrap(),
+        path: CString::new(format!("{}.dds", index)).unwrap(),
+        uv_layer: 0,
+        slot: *slot,
+      })
+      .collect();
+    mesh
+  }
+
+  #[test]
+  fn repeated_specular_uses_bump1_scale() {
+    let repeated = textured_mesh(42).pbr_material();
+    assert_eq!(repeated.specular.unwrap().tiling, 4_f32);
+    assert_eq!(repeated.normal.unwrap().tiling, 1_f32);
+    let plain = textured_mesh(40).pbr_material();
+    assert_eq!(plain.specular.unwrap().tiling, 1_f32);
+  }
+}
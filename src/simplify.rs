@@ -0,0 +1,643 @@
+use super::error_types::XpsError;
+use super::math;
+use super::types::{Data, Mesh, Vertex};
+use super::weld;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const SEAM_TOLERANCE: f32 = 1e-6;
+const POSITION_QUANTIZE: f32 = 1e5;
+const MIN_NORMAL_DOT: f32 = 0.2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum VertexKind {
+  Manifold,
+  Border,
+  Seam,
+  Locked,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+  fn from_plane(n: [f64; 3], d: f64, weight: f64) -> Quadric {
+    let [a, b, c] = n;
+    Quadric([
+      a * a * weight,
+      a * b * weight,
+      a * c * weight,
+      a * d * weight,
+      b * b * weight,
+      b * c * weight,
+      b * d * weight,
+      c * c * weight,
+      c * d * weight,
+      d * d * weight,
+    ])
+  }
+
+  fn add(&mut self, other: &Quadric) {
+    for (x, y) in self.0.iter_mut().zip(other.0.iter()) {
+      *x += *y;
+    }
+  }
+
+  fn error(&self, p: [f32; 3]) -> f64 {
+    let q = &self.0;
+    let (x, y, z) = (p[0] as f64, p[1] as f64, p[2] as f64);
+    q[0] * x * x
+      + 2.0 * q[1] * x * y
+      + 2.0 * q[2] * x * z
+      + 2.0 * q[3] * x
+      + q[4] * y * y
+      + 2.0 * q[5] * y * z
+      + 2.0 * q[6] * y
+      + q[7] * z * z
+      + 2.0 * q[8] * z
+      + q[9]
+  }
+}
+
+struct Candidate {
+  cost: f64,
+  from: u32,
+  to: u32,
+  stamp: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+  fn eq(&self, other: &Candidate) -> bool {
+    self.cost == other.cost
+  }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+  fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
+impl Ord for Candidate {
+  fn cmp(&self, other: &Candidate) -> Ordering {
+    other
+      .cost
+      .partial_cmp(&self.cost)
+      .unwrap_or(Ordering::Equal)
+  }
+}
+
+struct Simplifier<'a> {
+  vertices: &'a [Vertex],
+  triangles: Vec<[u32; 3]>,
+  deleted: Vec<bool>,
+  adjacency: Vec<Vec<usize>>,
+  quadrics: Vec<Quadric>,
+  kinds: Vec<VertexKind>,
+  seam_groups: Vec<Vec<u32>>,
+  seam_group_of: Vec<Option<usize>>,
+  removed: Vec<bool>,
+  versions: Vec<u32>,
+  border_edges: HashSet<(u32, u32)>,
+  heap: BinaryHeap<Candidate>,
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+  if a < b {
+    (a, b)
+  } else {
+    (b, a)
+  }
+}
+
+fn attribute_distance(a: &Vertex, b: &Vertex) -> f32 {
+  let color: f32 = a
+    .color
+    .iter()
+    .zip(b.color.iter())
+    .map(|(x, y)| (*x as f32 - *y as f32) / 255_f32)
+    .map(|x| x * x)
+    .sum();
+  let weights: f32 = a
+    .bone_weights
+    .iter()
+    .chain(b.bone_weights.iter())
+    .map(|x| {
+      let wa: f32 = a
+        .bone_weights
+        .iter()
+        .filter(|w| w.id == x.id)
+        .map(|w| w.weight)
+        .sum();
+      let wb: f32 = b
+        .bone_weights
+        .iter()
+        .filter(|w| w.id == x.id)
+        .map(|w| w.weight)
+        .sum();
+      (wa - wb) * (wa - wb)
+    })
+    .sum();
+  color + weights
+}
+
+impl<'a> Simplifier<'a> {
+  fn new(mesh: &'a Mesh, locked: &[bool]) -> Simplifier<'a> {
+    let vertices = &mesh.vertices[..];
+    let triangles: Vec<[u32; 3]> = mesh
+      .faces
+      .chunks_exact(3)
+      .filter(|x| x.iter().all(|i| (*i as usize) < vertices.len()))
+      .map(|x| [x[0], x[1], x[2]])
+      .collect();
+    let mut adjacency = vec![vec![]; vertices.len()];
+    let mut quadrics = vec![Quadric::default(); vertices.len()];
+    let mut edge_use: HashMap<(u32, u32), u32> = HashMap::new();
+    for (index, triangle) in triangles.iter().enumerate() {
+      let p: Vec<[f32; 3]> = triangle
+        .iter()
+        .map(|x| vertices[*x as usize].position)
+        .collect();
+      let n = math::cross(math::sub(p[1], p[0]), math::sub(p[2], p[0]));
+      let area = math::length(n);
+      if area > 0_f32 {
+        let n = math::scale(n, 1_f32 / area);
+        let plane = Quadric::from_plane(
+          [n[0] as f64, n[1] as f64, n[2] as f64],
+          -math::dot(n, p[0]) as f64,
+          area as f64,
+        );
+        for x in triangle {
+          quadrics[*x as usize].add(&plane);
+        }
+      }
+      for corner in 0..3 {
+        adjacency[triangle[corner] as usize].push(index);
+        let key = edge_key(triangle[corner], triangle[(corner + 1) % 3]);
+        *edge_use.entry(key).or_insert(0) += 1;
+      }
+    }
+
+    let mut kinds = vec![VertexKind::Manifold; vertices.len()];
+    let mut border_edges = HashSet::new();
+    for (edge, count) in edge_use {
+      let kind = match count {
+        1 => {
+          border_edges.insert(edge);
+          VertexKind::Border
+        }
+        2 => continue,
+        _ => VertexKind::Locked,
+      };
+      for x in [edge.0, edge.1].iter() {
+        if kinds[*x as usize] != VertexKind::Locked {
+          kinds[*x as usize] = kind;
+        }
+      }
+    }
+    let seam_groups = weld::seam_groups(mesh, SEAM_TOLERANCE);
+    let mut seam_group_of = vec![None; vertices.len()];
+    for (index, group) in seam_groups.iter().enumerate() {
+      for x in group {
+        seam_group_of[*x as usize] = Some(index);
+        if kinds[*x as usize] != VertexKind::Locked {
+          kinds[*x as usize] = VertexKind::Seam;
+        }
+      }
+    }
+    for (kind, lock) in kinds.iter_mut().zip(locked.iter()) {
+      if *lock {
+        *kind = VertexKind::Locked;
+      }
+    }
+
+    Simplifier {
+      vertices,
+      deleted: vec![false; triangles.len()],
+      triangles,
+      adjacency,
+      quadrics,
+      kinds,
+      seam_groups,
+      seam_group_of,
+      removed: vec![false; vertices.len()],
+      versions: vec![0; vertices.len()],
+      border_edges,
+      heap: BinaryHeap::new(),
+    }
+  }
+
+  fn neighbors(&self, vertex: u32) -> HashSet<u32> {
+    self.adjacency[vertex as usize]
+      .iter()
+      .filter(|x| !self.deleted[**x])
+      .flat_map(|x| self.triangles[*x].iter().cloned())
+      .filter(|x| *x != vertex)
+      .collect()
+  }
+
+  fn can_move(&self, from: u32, to: u32) -> bool {
+    match self.kinds[from as usize] {
+      VertexKind::Manifold => true,
+      VertexKind::Border | VertexKind::Seam => {
+        self.kinds[to as usize] == self.kinds[from as usize]
+          && self.border_edges.contains(&edge_key(from, to))
+      }
+      VertexKind::Locked => false,
+    }
+  }
+
+  fn push(&mut self, from: u32, to: u32) {
+    if !self.can_move(from, to) {
+      return;
+    }
+    let a = &self.vertices[from as usize];
+    let b = &self.vertices[to as usize];
+    let mut quadric = self.quadrics[from as usize];
+    quadric.add(&self.quadrics[to as usize]);
+    let edge = math::sub(a.position, b.position);
+    let cost = quadric.error(b.position).max(0.0)
+      + (math::dot(edge, edge) * attribute_distance(a, b)) as f64;
+    self.heap.push(Candidate {
+      cost,
+      from,
+      to,
+      stamp: (self.versions[from as usize], self.versions[to as usize]),
+    });
+  }
+
+  fn flips(&self, from: u32, to: u32) -> bool {
+    for triangle in self.adjacency[from as usize].iter() {
+      if self.deleted[*triangle] || self.triangles[*triangle].contains(&to) {
+        continue;
+      }
+      let corners = self.triangles[*triangle];
+      let p: Vec<[f32; 3]> = corners
+        .iter()
+        .map(|x| self.vertices[*x as usize].position)
+        .collect();
+      let moved: Vec<[f32; 3]> = corners
+        .iter()
+        .map(|x| self.vertices[if *x == from { to } else { *x } as usize].position)
+        .collect();
+      let before = math::cross(math::sub(p[1], p[0]), math::sub(p[2], p[0]));
+      let after = math::cross(math::sub(moved[1], moved[0]), math::sub(moved[2], moved[0]));
+      let after_length = math::length(after);
+      if after_length <= 0_f32
+        || math::dot(
+          math::normalize(before),
+          math::scale(after, 1_f32 / after_length),
+        ) < MIN_NORMAL_DOT
+      {
+        return true;
+      }
+    }
+    false
+  }
+
+  fn breaks_topology(&self, from: u32, to: u32) -> bool {
+    let shared_triangles = self.adjacency[from as usize]
+      .iter()
+      .filter(|x| !self.deleted[**x] && self.triangles[**x].contains(&to))
+      .count();
+    let from_neighbors = self.neighbors(from);
+    let common = self
+      .neighbors(to)
+      .iter()
+      .filter(|x| from_neighbors.contains(x))
+      .count();
+    shared_triangles == 0 || common > shared_triangles
+  }
+
+  fn seam_twins(&self, vertex: u32) -> impl Iterator<Item = u32> + '_ {
+    self.seam_group_of[vertex as usize]
+      .map(|x| &self.seam_groups[x][..])
+      .unwrap_or(&[])
+      .iter()
+      .cloned()
+      .filter(move |x| *x != vertex && !self.removed[*x as usize])
+  }
+
+  // A seam vertex only moves together with its twins on the other sides of
+  // the seam, each along its own copy of the seam edge, so both sides keep
+  // meeting at the same positions.
+  fn collapse_pairs(&self, from: u32, to: u32) -> Option<Vec<(u32, u32)>> {
+    let mut pairs = vec![(from, to)];
+    if self.kinds[from as usize] != VertexKind::Seam {
+      return Some(pairs);
+    }
+    for twin in self.seam_twins(from) {
+      let target = self.seam_twins(to).find(|x| {
+        self.border_edges.contains(&edge_key(twin, *x)) && !pairs.iter().any(|p| p.1 == *x)
+      })?;
+      if !self.can_move(twin, target) {
+        return None;
+      }
+      pairs.push((twin, target));
+    }
+    Some(pairs)
+  }
+
+  fn collapse(&mut self, from: u32, to: u32) -> usize {
+    let mut removed_triangles = 0;
+    let border_neighbors: Vec<u32> = self
+      .neighbors(from)
+      .into_iter()
+      .filter(|x| self.border_edges.contains(&edge_key(from, *x)))
+      .collect();
+    let triangles = std::mem::take(&mut self.adjacency[from as usize]);
+    for triangle in triangles {
+      if self.deleted[triangle] {
+        continue;
+      }
+      if self.triangles[triangle].contains(&to) {
+        self.deleted[triangle] = true;
+        removed_triangles += 1;
+        continue;
+      }
+      for corner in self.triangles[triangle].iter_mut() {
+        if *corner == from {
+          *corner = to;
+        }
+      }
+      self.adjacency[to as usize].push(triangle);
+    }
+    for x in border_neighbors {
+      self.border_edges.remove(&edge_key(from, x));
+      if x != to {
+        self.border_edges.insert(edge_key(to, x));
+      }
+    }
+    let quadric = self.quadrics[from as usize];
+    self.quadrics[to as usize].add(&quadric);
+    self.removed[from as usize] = true;
+    self.versions[to as usize] += 1;
+    for x in self.neighbors(to) {
+      self.push(to, x);
+      self.push(x, to);
+    }
+    removed_triangles
+  }
+
+  fn run(&mut self, target: usize) {
+    for triangle in 0..self.triangles.len() {
+      for corner in 0..3 {
+        let a = self.triangles[triangle][corner];
+        let b = self.triangles[triangle][(corner + 1) % 3];
+        self.push(a, b);
+        self.push(b, a);
+      }
+    }
+    let mut remaining = self.triangles.len();
+    while remaining > target {
+      let candidate = match self.heap.pop() {
+        Some(x) => x,
+        None => break,
+      };
+      let (from, to) = (candidate.from, candidate.to);
+      if self.removed[from as usize]
+        || self.removed[to as usize]
+        || candidate.stamp != (self.versions[from as usize], self.versions[to as usize])
+      {
+        continue;
+      }
+      let pairs = match self.collapse_pairs(from, to) {
+        Some(x) => x,
+        None => continue,
+      };
+      if pairs
+        .iter()
+        .any(|(from, to)| self.breaks_topology(*from, *to) || self.flips(*from, *to))
+      {
+        continue;
+      }
+      for (from, to) in pairs {
+        remaining -= self.collapse(from, to);
+      }
+    }
+  }
+
+  fn into_mesh(self, mesh: &Mesh) -> Mesh {
+    let mut remap: HashMap<u32, u32> = HashMap::new();
+    let mut vertices = vec![];
+    let mut faces = vec![];
+    for (triangle, deleted) in self.triangles.iter().zip(self.deleted.iter()) {
+      if *deleted {
+        continue;
+      }
+      for x in triangle {
+        let index = *remap.entry(*x).or_insert_with(|| {
+          vertices.push(self.vertices[*x as usize].clone());
+          vertices.len() as u32 - 1
+        });
+        faces.push(index);
+      }
+    }
+    Mesh {
+      name: mesh.name.clone(),
+      full_name: mesh.full_name.clone(),
+      textures: mesh.textures.clone(),
+      vertices,
+      faces,
+      uv_count: mesh.uv_count,
+      render_group: mesh.render_group.clone(),
+      render_parameters: mesh.render_parameters,
+      item: mesh.item.clone(),
+      camera: mesh.camera.clone(),
+      buffers: None,
+    }
+  }
+}
+
+fn valid_ratio(ratio: f32) -> bool {
+  ratio.is_finite() && ratio > 0_f32 && ratio <= 1_f32
+}
+
+// Non-manifold edges and `locked` vertices are never moved; open borders and
+// UV seams only collapse along themselves. `target_ratio` must lie in (0, 1].
+pub fn simplify_mesh(mesh: &Mesh, target_ratio: f32, locked: &[bool]) -> Result<Mesh, XpsError> {
+  if !valid_ratio(target_ratio) {
+    return Err(XpsError::InvalidArgument);
+  }
+  let buffered = mesh.buffers.is_some();
+  let mesh = &*mesh.unpacked();
+  let mut simplifier = Simplifier::new(mesh, locked);
+  let target = (simplifier.triangles.len() as f32 * target_ratio) as usize;
+  simplifier.run(target);
+  let mut simplified = simplifier.into_mesh(mesh);
+  if buffered {
+    simplified.pack_buffers();
+  }
+  Ok(simplified)
+}
+
+fn quantize(position: [f32; 3]) -> (i64, i64, i64) {
+  (
+    (position[0] * POSITION_QUANTIZE).round() as i64,
+    (position[1] * POSITION_QUANTIZE).round() as i64,
+    (position[2] * POSITION_QUANTIZE).round() as i64,
+  )
+}
+
+fn shared_border_locks(data: &Data, mesh_index: usize) -> Vec<bool> {
+  let item = &data.meshes[mesh_index].item.name;
+  let shared: HashSet<(i64, i64, i64)> = data
+    .meshes
+    .iter()
+    .enumerate()
+    .filter(|(index, x)| *index != mesh_index && x.item.name == *item)
+    .flat_map(|(_, x)| x.vertex_positions().map(|v| quantize(*v)))
+    .collect();
+  data.meshes[mesh_index]
+    .vertex_positions()
+    .map(|x| shared.contains(&quantize(*x)))
+    .collect()
+}
+
+pub fn generate_lods(data: &Data, ratios: &[f32]) -> Result<Vec<Vec<Mesh>>, XpsError> {
+  if !ratios.iter().all(|x| valid_ratio(*x)) {
+    return Err(XpsError::InvalidArgument);
+  }
+  (0..data.meshes.len())
+    .map(|index| {
+      let locked = shared_border_locks(data, index);
+      ratios
+        .iter()
+        .map(|ratio| simplify_mesh(&data.meshes[index], *ratio, &locked))
+        .collect::<Result<Vec<Mesh>, XpsError>>()
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  fn face_normals(mesh: &Mesh) -> Vec<[f32; 3]> {
+    mesh
+      .faces
+      .chunks_exact(3)
+      .map(|x| {
+        let p: Vec<[f32; 3]> = x
+          .iter()
+          .map(|i| mesh.vertices[*i as usize].position)
+          .collect();
+        math::cross(math::sub(p[1], p[0]), math::sub(p[2], p[0]))
+      })
+      .collect()
+  }
+
+  #[test]
+  fn reaches_target_triangle_count() {
+    let mesh = test_util::grid(12);
+    let triangles = mesh.faces.len() / 3;
+    let simplified = simplify_mesh(&mesh, 0.25, &[]).unwrap();
+    let target = triangles / 4;
+    let remaining = simplified.faces.len() / 3;
+    assert!(remaining <= target, "{} > {}", remaining, target);
+    assert!(remaining + 2 >= target, "{} < {}", remaining, target);
+  }
+
+  #[test]
+  fn never_flips_faces() {
+    let mut mesh = test_util::grid(12);
+    for vertex in mesh.vertices.iter_mut() {
+      let [x, y, _] = vertex.position;
+      vertex.position[2] = 0.2 * (x * 7_f32).sin() * (y * 5_f32).cos();
+    }
+    for ratio in [0.1_f32, 0.3, 0.6].iter() {
+      let simplified = simplify_mesh(&mesh, *ratio, &[]).unwrap();
+      assert!(!simplified.faces.is_empty());
+      for normal in face_normals(&simplified) {
+        assert!(normal[2] > 0_f32, "flipped face {:?}", normal);
+      }
+    }
+  }
+
+  // Grid split down x = 0.5 into two UV islands that share positions only.
+  fn seam_grid(n: u32) -> Mesh {
+    let mut mesh = test_util::grid(n);
+    let mut twins: HashMap<u32, u32> = HashMap::new();
+    for face in 0..mesh.faces.len() / 3 {
+      let corners = &mesh.faces[face * 3..face * 3 + 3];
+      let centroid: f32 = corners
+        .iter()
+        .map(|x| mesh.vertices[*x as usize].position[0])
+        .sum();
+      if centroid < 1.5 {
+        continue;
+      }
+      for corner in face * 3..face * 3 + 3 {
+        let index = mesh.faces[corner];
+        if (mesh.vertices[index as usize].position[0] - 0.5).abs() > 1e-6 {
+          continue;
+        }
+        let vertices = &mut mesh.vertices;
+        let twin = *twins.entry(index).or_insert_with(|| {
+          let mut vertex = vertices[index as usize].clone();
+          vertex.uv[0] = [1_f32, 0_f32];
+          vertices.push(vertex);
+          vertices.len() as u32 - 1
+        });
+        mesh.faces[corner] = twin;
+      }
+    }
+    mesh
+  }
+
+  fn seam_positions(mesh: &Mesh, right: bool) -> HashSet<(i64, i64, i64)> {
+    mesh
+      .faces
+      .chunks_exact(3)
+      .filter(|x| {
+        let centroid: f32 = x
+          .iter()
+          .map(|i| mesh.vertices[*i as usize].position[0])
+          .sum();
+        (centroid > 1.5) == right
+      })
+      .flat_map(|x| x.iter())
+      .map(|x| mesh.vertices[*x as usize].position)
+      .filter(|x| (x[0] - 0.5).abs() < 1e-6)
+      .map(quantize)
+      .collect()
+  }
+
+  #[test]
+  fn seams_collapse_on_both_sides() {
+    let mesh = seam_grid(12);
+    assert_eq!(weld::seam_groups(&mesh, SEAM_TOLERANCE).len(), 13);
+    // Keep the outer border so neither island can erode away.
+    let locked: Vec<bool> = mesh
+      .vertices
+      .iter()
+      .map(|x| x.position[..2].iter().any(|v| *v == 0_f32 || *v == 1_f32))
+      .collect();
+    let simplified = simplify_mesh(&mesh, 0.02, &locked).unwrap();
+    let left = seam_positions(&simplified, false);
+    let right = seam_positions(&simplified, true);
+    assert_eq!(left, right);
+    assert!(left.len() < 13, "seam kept {} vertices", left.len());
+  }
+
+  #[test]
+  fn rejects_invalid_ratios() {
+    let mesh = test_util::grid(2);
+    for ratio in [0_f32, -0.5, 1.5, f32::NAN, f32::INFINITY].iter() {
+      assert!(simplify_mesh(&mesh, *ratio, &[]).is_err());
+    }
+    assert_eq!(
+      simplify_mesh(&mesh, 1_f32, &[]).unwrap().faces.len(),
+      mesh.faces.len()
+    );
+    let data = Data {
+      meshes: vec![mesh],
+      ..Default::default()
+    };
+    assert!(generate_lods(&data, &[0.5, 0_f32]).is_err());
+    assert_eq!(generate_lods(&data, &[0.5, 1_f32]).unwrap()[0].len(), 2);
+  }
+}
@@ -0,0 +1,272 @@
+use super::bone_naming::{BoneType, Converter};
+use super::math;
+use super::math::{Mat4, Quat, Vec3};
+use super::types::{BonePose, Data};
+
+const FORWARD: Vec3 = [0_f32, 0_f32, 1_f32];
+const UP: Vec3 = [0_f32, 1_f32, 0_f32];
+const MIN_BONE_LENGTH: f32 = 1e-5;
+
+pub struct BoneRest {
+  pub local_translation: [f32; 3],
+  pub world_matrix: Mat4,
+  pub inverse_bind_matrix: Mat4,
+}
+
+impl Data {
+  pub fn bone_parent(&self, index: usize) -> Option<usize> {
+    let parent = self.bones.get(index)?.parent_id;
+    if parent < 0 || parent as usize >= self.bones.len() || parent as usize == index {
+      None
+    } else {
+      Some(parent as usize)
+    }
+  }
+
+  // Parents always come before their children. Bones caught in a parent
+  // cycle are emitted as roots once nothing else can be visited. The loader
+  // stores the result in `Data::bone_order`.
+  pub fn collect_bone_order(&self) -> Vec<usize> {
+    let mut children = vec![vec![]; self.bones.len()];
+    let mut roots = vec![];
+    for index in 0..self.bones.len() {
+      match self.bone_parent(index) {
+        Some(x) => children[x].push(index),
+        None => roots.push(index),
+      }
+    }
+    let mut order = Vec::with_capacity(self.bones.len());
+    let mut visited = vec![false; self.bones.len()];
+    let mut start = 0;
+    loop {
+      let mut stack: Vec<usize> = roots.drain(..).rev().collect();
+      while let Some(x) = stack.pop() {
+        if visited[x] {
+          continue;
+        }
+        visited[x] = true;
+        order.push(x);
+        stack.extend(children[x].iter().rev());
+      }
+      match (start..self.bones.len()).find(|x| !visited[*x]) {
+        Some(x) => {
+          start = x;
+          roots.push(x);
+        }
+        None => break,
+      }
+    }
+    order
+  }
+
+  // Call after changing the bone hierarchy so the cached order matches again.
+  pub fn update_bone_order(&mut self) {
+    self.bone_order = self.collect_bone_order();
+  }
+
+  pub fn bone_world_matrix(&self, index: usize) -> Mat4 {
+    let bone = &self.bones[index];
+    math::mat4_from_rotation_translation(bone.rest_rotation, bone.co)
+  }
+
+  pub fn bone_local_rotation(&self, index: usize) -> Quat {
+    let rotation = self.bones[index].rest_rotation;
+    match self.bone_parent(index) {
+      Some(parent) => math::quat_mul(math::quat_conjugate(self.bones[parent].rest_rotation), rotation),
+      None => rotation,
+    }
+  }
+
+  // XPS pose deltas rotate the bone around world-aligned axes, so with
+  // oriented bones the delta is moved into the parent's rest frame.
+  pub fn posed_local_transform(&self, index: usize, pose: Option<&BonePose>) -> (Vec3, Quat) {
+    let bone = &self.bones[index];
+    let (parent_co, parent_rotation) = match self.bone_parent(index) {
+      Some(parent) => (self.bones[parent].co, self.bones[parent].rest_rotation),
+      None => ([0_f32; 3], math::QUAT_IDENTITY),
+    };
+    let (delta_position, delta_rotation) = match pose {
+      Some(x) => (x.coordinate_delta, math::quat_from_xps_euler(x.rotation_delta)),
+      None => ([0_f32; 3], math::QUAT_IDENTITY),
+    };
+    let inverse_parent = math::quat_conjugate(parent_rotation);
+    let position = math::add(math::sub(bone.co, parent_co), delta_position);
+    (
+      math::quat_rotate(inverse_parent, position),
+      math::quat_mul(inverse_parent, math::quat_mul(delta_rotation, bone.rest_rotation)),
+    )
+  }
+
+  pub fn bone_local_translation(&self, index: usize) -> [f32; 3] {
+    let world = self.bone_world_matrix(index);
+    let position = [world[3][0], world[3][1], world[3][2]];
+    match self.bone_parent(index) {
+      Some(parent) => {
+        let local = math::mat4_mul(
+          &math::mat4_inverse_rigid(&self.bone_world_matrix(parent)),
+          &world,
+        );
+        [local[3][0], local[3][1], local[3][2]]
+      }
+      None => position,
+    }
+  }
+
+  pub fn rest_transforms(&self) -> Vec<BoneRest> {
+    (0..self.bones.len())
+      .map(|index| {
+        let world_matrix = self.bone_world_matrix(index);
+        BoneRest {
+          local_translation: self.bone_local_translation(index),
+          inverse_bind_matrix: math::mat4_inverse_rigid(&world_matrix),
+          world_matrix,
+        }
+      })
+      .collect()
+  }
+}
+
+fn is_chain_child(bone_type: BoneType) -> bool {
+  matches!(
+    bone_type,
+    BoneType::SpineLower
+      | BoneType::SpineMiddle
+      | BoneType::SpineUpper
+      | BoneType::Neck
+      | BoneType::Head
+      | BoneType::MiddleLeft0
+      | BoneType::MiddleRight0
+  )
+}
+
+// Fingers and feet roll around world up, everything else around forward.
+fn roll_reference(bone_type: Option<BoneType>) -> Vec3 {
+  match bone_type {
+    Some(BoneType::FootLeft)
+    | Some(BoneType::FootRight)
+    | Some(BoneType::ToeLeft)
+    | Some(BoneType::ToeRight)
+    | Some(BoneType::HandLeft)
+    | Some(BoneType::HandRight)
+    | Some(BoneType::ThumbLeft0)
+    | Some(BoneType::ThumbLeft1)
+    | Some(BoneType::ThumbLeft2)
+    | Some(BoneType::IndexLeft0)
+    | Some(BoneType::IndexLeft1)
+    | Some(BoneType::IndexLeft2)
+    | Some(BoneType::MiddleLeft0)
+    | Some(BoneType::MiddleLeft1)
+    | Some(BoneType::MiddleLeft2)
+    | Some(BoneType::RingLeft0)
+    | Some(BoneType::RingLeft1)
+    | Some(BoneType::RingLeft2)
+    | Some(BoneType::PinkyLeft0)
+    | Some(BoneType::PinkyLeft1)
+    | Some(BoneType::PinkyLeft2)
+    | Some(BoneType::ThumbRight0)
+    | Some(BoneType::ThumbRight1)
+    | Some(BoneType::ThumbRight2)
+    | Some(BoneType::IndexRight0)
+    | Some(BoneType::IndexRight1)
+    | Some(BoneType::IndexRight2)
+    | Some(BoneType::MiddleRight0)
+    | Some(BoneType::MiddleRight1)
+    | Some(BoneType::MiddleRight2)
+    | Some(BoneType::RingRight0)
+    | Some(BoneType::RingRight1)
+    | Some(BoneType::RingRight2)
+    | Some(BoneType::PinkyRight0)
+    | Some(BoneType::PinkyRight1)
+    | Some(BoneType::PinkyRight2) => UP,
+    _ => FORWARD,
+  }
+}
+
+// The bone's +Y axis points along `direction` and +Z is kept as close to
+// `reference` as possible.
+fn aim(direction: Vec3, reference: Vec3) -> Option<Quat> {
+  if math::length(direction) < MIN_BONE_LENGTH {
+    return None;
+  }
+  let y = math::normalize(direction);
+  let mut reference = reference;
+  if math::length(math::cross(y, reference)) < 1e-3 {
+    reference = if reference == UP { FORWARD } else { UP };
+  }
+  let x = math::normalize(math::cross(y, reference));
+  let z = math::cross(x, y);
+  Some(math::quat_from_basis(x, y, z))
+}
+
+// Computes `Bone::rest_rotation` so each joint points at its child. Leaf
+// bones inherit the orientation of their parent.
+pub fn orient_bones(data: &mut Data, average_children: bool) {
+  let converter = Converter::new();
+  let bone_types: Vec<Option<BoneType>> = data
+    .bones
+    .iter()
+    .map(|x| {
+      converter
+        .bone_dictionary
+        .get(x.name.to_str().unwrap_or(""))
+        .cloned()
+    })
+    .collect();
+  let mut children = vec![vec![]; data.bones.len()];
+  for index in 0..data.bones.len() {
+    if let Some(parent) = data.bone_parent(index) {
+      children[parent].push(index);
+    }
+  }
+  for index in data.collect_bone_order() {
+    let co = data.bones[index].co;
+    let chain_child = children[index]
+      .iter()
+      .find(|x| bone_types[**x].map(is_chain_child).unwrap_or(false));
+    let direction = match (chain_child, children[index].first()) {
+      (Some(child), _) => math::sub(data.bones[*child].co, co),
+      (None, Some(_)) if average_children => {
+        let sum = children[index]
+          .iter()
+          .fold([0_f32; 3], |acc, x| math::add(acc, data.bones[*x].co));
+        math::sub(math::scale(sum, 1_f32 / children[index].len() as f32), co)
+      }
+      (None, Some(child)) => math::sub(data.bones[*child].co, co),
+      (None, None) => [0_f32; 3],
+    };
+    let inherited = match data.bone_parent(index) {
+      Some(parent) => data.bones[parent].rest_rotation,
+      None => math::QUAT_IDENTITY,
+    };
+    data.bones[index].rest_rotation =
+      aim(direction, roll_reference(bone_types[index])).unwrap_or(inherited);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::types::Bone;
+  use std::ffi::CString;
+
+  fn bone(id: i16, parent_id: i16) -> Bone {
+    Bone {
+      id,
+      name: CString::new(format!("bone{}", id)).unwrap(),
+      co: [0_f32; 3],
+      parent_id,
+      rest_rotation: math::QUAT_IDENTITY,
+    }
+  }
+
+  #[test]
+  fn stored_order_lists_parents_first() {
+    let mut data = Data {
+      bones: vec![bone(0, 2), bone(1, -1), bone(2, 1), bone(3, 4), bone(4, 3)],
+      ..Default::default()
+    };
+    assert!(data.bone_order.is_empty());
+    data.update_bone_order();
+    assert_eq!(data.bone_order, [1, 2, 0, 3, 4]);
+  }
+}
@@ -0,0 +1,128 @@
+use super::types::{BoneWeight, Data, Mesh, Vertex};
+
+// Influences per vertex in files without variable weights.
+pub const MAX_INFLUENCES: usize = 4;
+
+// Sorts influences by weight, merges repeated bones, drops the ones at or
+// below `threshold`, keeps at most `max_influences` and renormalises.
+// The vertex keeps its number of slots; unused ones end up as bone 0 with
+// zero weight.
+pub fn normalize_weights(bone_weights: &mut [BoneWeight], threshold: f32, max_influences: usize) {
+  let mut influences: Vec<BoneWeight> = vec![];
+  for bone_weight in bone_weights.iter() {
+    if !bone_weight.weight.is_finite() || bone_weight.weight <= 0_f32 {
+      continue;
+    }
+    match influences.iter_mut().find(|x| x.id == bone_weight.id) {
+      Some(x) => x.weight += bone_weight.weight,
+      None => influences.push(*bone_weight),
+    }
+  }
+  influences.sort_by(|a, b| {
+    b.weight
+      .partial_cmp(&a.weight)
+      .unwrap_or(std::cmp::Ordering::Equal)
+  });
+  let strongest = influences.first().cloned();
+  influences.retain(|x| x.weight > threshold);
+  influences.truncate(max_influences.max(1));
+  if influences.is_empty() {
+    influences.extend(strongest);
+  }
+
+  let total: f32 = influences.iter().map(|x| x.weight).sum();
+  for (slot, bone_weight) in bone_weights.iter_mut().enumerate() {
+    *bone_weight = match influences.get(slot) {
+      Some(x) if total > 0_f32 => BoneWeight {
+        id: x.id,
+        weight: x.weight / total,
+      },
+      _ => BoneWeight::default(),
+    };
+  }
+}
+
+pub fn normalize_vertex_weights(vertex: &mut Vertex, threshold: f32, max_influences: usize) {
+  normalize_weights(&mut vertex.bone_weights, threshold, max_influences);
+}
+
+pub fn normalize_mesh_weights(mesh: &mut Mesh, threshold: f32, max_influences: usize) {
+  for vertex in mesh.vertices.iter_mut() {
+    normalize_vertex_weights(vertex, threshold, max_influences);
+  }
+  if let Some(buffers) = mesh.buffers.as_mut() {
+    for index in 0..buffers.vertex_count() {
+      let mut bone_weights = buffers.bone_weights(index);
+      normalize_weights(&mut bone_weights, threshold, max_influences);
+      buffers.set_bone_weights(index, &bone_weights);
+    }
+  }
+}
+
+pub fn normalize_model_weights(data: &mut Data, threshold: f32, max_influences: usize) {
+  if data.bones.is_empty() {
+    return;
+  }
+  for mesh in data.meshes.iter_mut() {
+    normalize_mesh_weights(mesh, threshold, max_influences);
+  }
+}
+
+impl Vertex {
+  pub fn influence_count(&self) -> usize {
+    self.bone_weights.iter().filter(|x| x.weight > 0_f32).count()
+  }
+}
+
+impl Mesh {
+  pub fn max_influences(&self) -> usize {
+    (0..self.vertex_count())
+      .map(|index| {
+        (0..self.vertex_bone_weight_count(index))
+          .filter(|x| self.vertex_bone_weight(index, *x).weight > 0_f32)
+          .count()
+      })
+      .max()
+      .unwrap_or(0)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  fn weight(id: i16, weight: f32) -> BoneWeight {
+    BoneWeight { id, weight }
+  }
+
+  #[test]
+  fn normalizes_prunes_and_limits_six_influences() {
+    let mut vertex = test_util::vertex([0_f32; 3], [0_f32, 0_f32, 1_f32]);
+    vertex.bone_weights = vec![
+      weight(3, 0.1),
+      weight(1, 0.4),
+      weight(5, 0.01),
+      weight(2, 0.2),
+      weight(1, 0.2),
+      weight(4, f32::NAN),
+    ];
+    normalize_vertex_weights(&mut vertex, 0.05, 2);
+
+    assert_eq!(vertex.bone_weights.len(), 6);
+    assert_eq!(vertex.influence_count(), 2);
+    assert_eq!(vertex.bone_weights[0].id, 1);
+    assert_eq!(vertex.bone_weights[1].id, 2);
+    assert!((vertex.bone_weights[0].weight - 0.75).abs() < 1e-6);
+    assert!((vertex.bone_weights[1].weight - 0.25).abs() < 1e-6);
+    for slot in &vertex.bone_weights[2..] {
+      assert_eq!((slot.id, slot.weight), (0, 0_f32));
+    }
+
+    let mut mesh = test_util::mesh(vec![vertex.clone(), vertex], vec![]);
+    assert_eq!(mesh.max_influences(), 2);
+    normalize_mesh_weights(&mut mesh, 0.5, 4);
+    assert_eq!(mesh.max_influences(), 1);
+    assert_eq!(mesh.vertices[0].bone_weights[0].weight, 1_f32);
+  }
+}
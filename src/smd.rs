@@ -0,0 +1,223 @@
+use super::error_types::XpsError;
+use super::math;
+use super::types::{BonePose, Data, Mesh, Vertex};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+
+const MAX_LINKS: usize = 4;
+
+fn material_name(mesh: &Mesh) -> String {
+  let diffuse = mesh.pbr_material().base_color.map(|x| x.texture_index);
+  if let Some(texture) = diffuse.and_then(|x| mesh.textures.get(x)) {
+    if let Some(stem) = Path::new(texture.file.to_str().unwrap_or("")).file_stem() {
+      if let Some(x) = stem.to_str() {
+        return x.to_string();
+      }
+    }
+  }
+  mesh.name.to_str().unwrap_or("default").to_string()
+}
+
+// SMD readers expect every parent to be listed before its children, which
+// the file order of an XPS skeleton does not guarantee.
+fn write_nodes(out: &mut String, data: &Data) {
+  out.push_str("nodes\n");
+  if data.bones.is_empty() {
+    out.push_str("0 \"root\" -1\n");
+  }
+  for index in data.collect_bone_order() {
+    let bone = &data.bones[index];
+    let _ = writeln!(
+      out,
+      "{} \"{}\" {}",
+      bone.id,
+      bone.name.to_string_lossy().replace('"', "'"),
+      bone.parent_id
+    );
+  }
+  out.push_str("end\n");
+}
+
+fn write_frame(
+  out: &mut String,
+  time: usize,
+  data: &Data,
+  pose: Option<&HashMap<String, BonePose>>,
+) {
+  let _ = writeln!(out, "time {}", time);
+  if data.bones.is_empty() {
+    out.push_str("0 0.000000 0.000000 0.000000 0.000000 0.000000 0.000000\n");
+  }
+  for index in data.collect_bone_order() {
+    let bone = &data.bones[index];
+    let bone_pose = pose.and_then(|p| p.get(bone.name.to_str().unwrap_or("")));
+    let (position, rotation) = data.posed_local_transform(index, bone_pose);
+    let rotation = math::quat_to_euler_xyz(rotation);
+    let _ = writeln!(
+      out,
+      "{} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6}",
+      bone.id, position[0], position[1], position[2], rotation[0], rotation[1], rotation[2]
+    );
+  }
+}
+
+fn write_vertex(out: &mut String, vertex: &Vertex, bone_count: usize) {
+  let links: Vec<_> = vertex
+    .bone_weights
+    .iter()
+    .filter(|x| x.weight > 0_f32 && x.id >= 0 && (x.id as usize) < bone_count)
+    .take(MAX_LINKS)
+    .collect();
+  let parent = links.first().map(|x| x.id).unwrap_or(0);
+  let uv = vertex.uv.first().cloned().unwrap_or_default();
+  let _ = write!(
+    out,
+    "{} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {:.6} {}",
+    parent,
+    vertex.position[0],
+    vertex.position[1],
+    vertex.position[2],
+    vertex.normal[0],
+    vertex.normal[1],
+    vertex.normal[2],
+    uv[0],
+    uv[1],
+    links.len()
+  );
+  for link in links {
+    let _ = write!(out, " {} {:.6}", link.id, link.weight);
+  }
+  out.push('\n');
+}
+
+pub fn reference_to_string(data: &Data) -> String {
+  let mut out = String::from("version 1\n");
+  write_nodes(&mut out, data);
+  out.push_str("skeleton\n");
+  write_frame(&mut out, 0, data, None);
+  out.push_str("end\n");
+  out.push_str("triangles\n");
+  for mesh in &data.meshes {
+    let mesh = &*mesh.unpacked();
+    let material = material_name(mesh);
+    for face in mesh.faces.chunks_exact(3) {
+      if face.iter().any(|x| *x as usize >= mesh.vertices.len()) {
+        continue;
+      }
+      let _ = writeln!(out, "{}", material);
+      for index in face {
+        write_vertex(&mut out, &mesh.vertices[*index as usize], data.bones.len());
+      }
+    }
+  }
+  out.push_str("end\n");
+  out
+}
+
+pub fn animation_to_string(data: &Data, frames: &[HashMap<String, BonePose>]) -> String {
+  let mut out = String::from("version 1\n");
+  write_nodes(&mut out, data);
+  out.push_str("skeleton\n");
+  for (time, pose) in frames.iter().enumerate() {
+    write_frame(&mut out, time, data, Some(pose));
+  }
+  out.push_str("end\n");
+  out
+}
+
+pub fn write_reference(data: &Data, filename: &str) -> Result<(), XpsError> {
+  std::fs::write(filename, reference_to_string(data)).map_err(|_| XpsError::FileNotWritten)
+}
+
+pub fn write_animation(
+  data: &Data,
+  frames: &[HashMap<String, BonePose>],
+  filename: &str,
+) -> Result<(), XpsError> {
+  std::fs::write(filename, animation_to_string(data, frames)).map_err(|_| XpsError::FileNotWritten)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+  use crate::types::Bone;
+  use std::ffi::CString;
+
+  fn bone(id: i16, name: &str, parent_id: i16, co: [f32; 3]) -> Bone {
+    Bone {
+      id,
+      name: CString::new(name).unwrap(),
+      co,
+      parent_id,
+      rest_rotation: math::QUAT_IDENTITY,
+    }
+  }
+
+  fn block<'a>(text: &'a str, name: &str) -> Vec<&'a str> {
+    text
+      .lines()
+      .skip_while(|x| *x != name)
+      .skip(1)
+      .take_while(|x| *x != "end")
+      .collect()
+  }
+
+  #[test]
+  fn parents_are_written_before_children() {
+    let mut mesh = test_util::mesh(
+      vec![
+        test_util::vertex([0_f32, 0_f32, 0_f32], [0_f32, 0_f32, 1_f32]),
+        test_util::vertex([1_f32, 0_f32, 0_f32], [0_f32, 0_f32, 1_f32]),
+        test_util::vertex([0_f32, 1_f32, 0_f32], [0_f32, 0_f32, 1_f32]),
+      ],
+      vec![0, 1, 2],
+    );
+    mesh.vertices[2].bone_weights[0].id = 1;
+    let data = Data {
+      bones: vec![
+        bone(0, "child", 1, [0_f32, 1_f32, 0_f32]),
+        bone(1, "root", -1, [0_f32, 0_f32, 0_f32]),
+      ],
+      meshes: vec![mesh],
+      ..Default::default()
+    };
+
+    let reference = reference_to_string(&data);
+    assert_eq!(
+      block(&reference, "nodes"),
+      ["1 \"root\" -1", "0 \"child\" 1"]
+    );
+    let skeleton = block(&reference, "skeleton");
+    assert_eq!(skeleton[0], "time 0");
+    assert!(skeleton[1].starts_with("1 0.000000 0.000000 0.000000"));
+    assert!(skeleton[2].starts_with("0 0.000000 1.000000 0.000000"));
+    let triangles = block(&reference, "triangles");
+    assert_eq!(triangles.len(), 4);
+    assert_eq!(triangles[0], "mesh");
+    assert!(triangles[3].starts_with("1 0.000000 1.000000 0.000000"));
+    assert!(triangles[3].ends_with(" 1 1 1.000000"));
+
+    let mut pose = HashMap::new();
+    pose.insert(
+      "root".to_string(),
+      BonePose {
+        name: "root".to_string(),
+        coordinate_delta: [2_f32, 0_f32, 0_f32],
+        rotation_delta: [0_f32; 3],
+        scale: [1_f32; 3],
+      },
+    );
+    let animation = animation_to_string(&data, &[HashMap::new(), pose]);
+    assert_eq!(
+      block(&animation, "nodes"),
+      ["1 \"root\" -1", "0 \"child\" 1"]
+    );
+    let frames = block(&animation, "skeleton");
+    assert_eq!(frames.len(), 6);
+    assert_eq!(frames[3], "time 1");
+    assert!(frames[4].starts_with("1 2.000000 0.000000 0.000000"));
+    assert!(frames[5].starts_with("0 0.000000 1.000000 0.000000"));
+  }
+}
@@ -0,0 +1,123 @@
+use super::material::RenderGroup;
+use super::types::{BoneWeight, Mesh, MeshItem, RenderParameters, Vertex};
+use std::ffi::CString;
+
+pub fn vertex(position: [f32; 3], normal: [f32; 3]) -> Vertex {
+  Vertex {
+    position,
+    normal,
+    color: [255; 4],
+    uv: vec![[0_f32; 2]],
+    bone_weights: vec![BoneWeight {
+      id: 0,
+      weight: 1_f32,
+    }],
+    merged: false,
+  }
+}
+
+pub fn mesh(vertices: Vec<Vertex>, faces: Vec<u32>) -> Mesh {
+  Mesh {
+    name: CString::new("mesh").unwrap(),
+    full_name: CString::new("1_mesh").unwrap(),
+    textures: vec![],
+    vertices,
+    faces,
+    uv_count: 1,
+    render_group: RenderGroup::new(1),
+    render_parameters: RenderParameters::default(),
+    item: MeshItem {
+      name: CString::new("mesh").unwrap(),
+      optional: false,
+      visible_by_default: true,
+    },
+    camera: None,
+    buffers: None,
+  }
+}
+
+// Unit cube with four vertices per side. With `smooth` every corner gets the
+// same normal on all three sides, otherwise each side has its own.
+pub fn cube(smooth: bool) -> Mesh {
+  let sides: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+    (
+      [1_f32, 0_f32, 0_f32],
+      [0_f32, 1_f32, 0_f32],
+      [0_f32, 0_f32, 1_f32],
+    ),
+    (
+      [-1_f32, 0_f32, 0_f32],
+      [0_f32, 0_f32, 1_f32],
+      [0_f32, 1_f32, 0_f32],
+    ),
+    (
+      [0_f32, 1_f32, 0_f32],
+      [0_f32, 0_f32, 1_f32],
+      [1_f32, 0_f32, 0_f32],
+    ),
+    (
+      [0_f32, -1_f32, 0_f32],
+      [1_f32, 0_f32, 0_f32],
+      [0_f32, 0_f32, 1_f32],
+    ),
+    (
+      [0_f32, 0_f32, 1_f32],
+      [1_f32, 0_f32, 0_f32],
+      [0_f32, 1_f32, 0_f32],
+    ),
+    (
+      [0_f32, 0_f32, -1_f32],
+      [0_f32, 1_f32, 0_f32],
+      [1_f32, 0_f32, 0_f32],
+    ),
+  ];
+  let mut vertices = vec![];
+  let mut faces = vec![];
+  for (normal, u, v) in sides.iter() {
+    let base = vertices.len() as u32;
+    for (a, b) in [
+      (-1_f32, -1_f32),
+      (1_f32, -1_f32),
+      (1_f32, 1_f32),
+      (-1_f32, 1_f32),
+    ]
+    .iter()
+    {
+      let position = [
+        0.5 * (normal[0] + a * u[0] + b * v[0]),
+        0.5 * (normal[1] + a * u[1] + b * v[1]),
+        0.5 * (normal[2] + a * u[2] + b * v[2]),
+      ];
+      let normal = if smooth {
+        super::math::normalize(position)
+      } else {
+        *normal
+      };
+      vertices.push(vertex(position, normal));
+    }
+    faces.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+  }
+  mesh(vertices, faces)
+}
+
+// Flat `n` x `n` grid of quads in the XY plane.
+pub fn grid(n: u32) -> Mesh {
+  let mut vertices = vec![];
+  let mut faces = vec![];
+  for y in 0..=n {
+    for x in 0..=n {
+      vertices.push(vertex(
+        [x as f32 / n as f32, y as f32 / n as f32, 0_f32],
+        [0_f32, 0_f32, 1_f32],
+      ));
+    }
+  }
+  for y in 0..n {
+    for x in 0..n {
+      let a = y * (n + 1) + x;
+      let c = a + n + 1;
+      faces.extend_from_slice(&[a, a + 1, c + 1, a, c + 1, c]);
+    }
+  }
+  mesh(vertices, faces)
+}
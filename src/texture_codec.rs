@@ -0,0 +1,1021 @@
+use super::error_types::XpsError;
+use super::texture_resolver::TextureResolver;
+use super::types::Data;
+use byteorder::{ByteOrder, LittleEndian};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+// 8-bit RGBA, rows top to bottom.
+#[derive(Clone)]
+pub struct Image {
+  pub width: u32,
+  pub height: u32,
+  pub pixels: Vec<u8>,
+}
+
+impl Image {
+  pub fn new(width: u32, height: u32) -> Image {
+    Image {
+      width,
+      height,
+      pixels: vec![0; width as usize * height as usize * 4],
+    }
+  }
+
+  pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+    let i = (y as usize * self.width as usize + x as usize) * 4;
+    [
+      self.pixels[i],
+      self.pixels[i + 1],
+      self.pixels[i + 2],
+      self.pixels[i + 3],
+    ]
+  }
+
+  pub fn set_pixel(&mut self, x: u32, y: u32, value: [u8; 4]) {
+    let i = (y as usize * self.width as usize + x as usize) * 4;
+    self.pixels[i..i + 4].copy_from_slice(&value);
+  }
+
+  // Nearest-neighbour lookup so maps of different sizes can be combined.
+  fn sample(&self, x: u32, y: u32, width: u32, height: u32) -> [u8; 4] {
+    let sx = (x as u64 * self.width as u64 / width.max(1) as u64) as u32;
+    let sy = (y as u64 * self.height as u64 / height.max(1) as u64) as u32;
+    self.pixel(sx.min(self.width - 1), sy.min(self.height - 1))
+  }
+}
+
+// DDS
+
+const DDS_MAGIC: &[u8] = b"DDS ";
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_FOURCC: u32 = 0x4;
+
+#[derive(Clone, Copy, PartialEq)]
+enum BlockFormat {
+  Bc1,
+  Bc2,
+  Bc3,
+  Bc5,
+}
+
+impl BlockFormat {
+  fn block_size(self) -> usize {
+    match self {
+      BlockFormat::Bc1 => 8,
+      _ => 16,
+    }
+  }
+}
+
+fn expand_565(c: u16) -> [u8; 4] {
+  let r = ((c >> 11) & 0x1f) as u32;
+  let g = ((c >> 5) & 0x3f) as u32;
+  let b = (c & 0x1f) as u32;
+  [
+    (r * 255 / 31) as u8,
+    (g * 255 / 63) as u8,
+    (b * 255 / 31) as u8,
+    255,
+  ]
+}
+
+fn mix(a: [u8; 4], b: [u8; 4], wa: u32, wb: u32) -> [u8; 4] {
+  let mut out = [0; 4];
+  for (i, x) in out.iter_mut().enumerate() {
+    *x = ((a[i] as u32 * wa + b[i] as u32 * wb) / (wa + wb)) as u8;
+  }
+  out
+}
+
+fn color_block(block: &[u8], punch_through: bool) -> [[u8; 4]; 16] {
+  let c0 = LittleEndian::read_u16(&block[0..2]);
+  let c1 = LittleEndian::read_u16(&block[2..4]);
+  let a = expand_565(c0);
+  let b = expand_565(c1);
+  let palette = if c0 > c1 || !punch_through {
+    [a, b, mix(a, b, 2, 1), mix(a, b, 1, 2)]
+  } else {
+    [a, b, mix(a, b, 1, 1), [0, 0, 0, 0]]
+  };
+  let indices = LittleEndian::read_u32(&block[4..8]);
+  let mut out = [[0; 4]; 16];
+  for (i, x) in out.iter_mut().enumerate() {
+    *x = palette[((indices >> (i * 2)) & 3) as usize];
+  }
+  out
+}
+
+fn alpha_block(block: &[u8]) -> [u8; 16] {
+  let a0 = block[0] as u32;
+  let a1 = block[1] as u32;
+  let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+  if a0 > a1 {
+    for i in 1..7 {
+      palette[i + 1] = ((7 - i as u32) * a0 + i as u32 * a1) / 7;
+    }
+  } else {
+    for i in 1..5 {
+      palette[i + 1] = ((5 - i as u32) * a0 + i as u32 * a1) / 5;
+    }
+    palette[6] = 0;
+  }
+  let mut bits = 0_u64;
+  for (i, x) in block[2..8].iter().enumerate() {
+    bits |= (*x as u64) << (i * 8);
+  }
+  let mut out = [0; 16];
+  for (i, x) in out.iter_mut().enumerate() {
+    *x = palette[((bits >> (i * 3)) & 7) as usize] as u8;
+  }
+  out
+}
+
+fn decode_block(format: BlockFormat, block: &[u8]) -> [[u8; 4]; 16] {
+  match format {
+    BlockFormat::Bc1 => color_block(block, true),
+    BlockFormat::Bc2 => {
+      let mut out = color_block(&block[8..16], false);
+      for (i, x) in out.iter_mut().enumerate() {
+        let nibble = (block[i / 2] >> ((i % 2) * 4)) & 0xf;
+        x[3] = nibble * 17;
+      }
+      out
+    }
+    BlockFormat::Bc3 => {
+      let mut out = color_block(&block[8..16], false);
+      let alpha = alpha_block(&block[0..8]);
+      for (x, a) in out.iter_mut().zip(alpha.iter()) {
+        x[3] = *a;
+      }
+      out
+    }
+    // Two-channel normal maps: Z is rebuilt from X and Y.
+    BlockFormat::Bc5 => {
+      let red = alpha_block(&block[0..8]);
+      let green = alpha_block(&block[8..16]);
+      let mut out = [[0; 4]; 16];
+      for (i, x) in out.iter_mut().enumerate() {
+        let nx = red[i] as f32 / 127.5 - 1_f32;
+        let ny = green[i] as f32 / 127.5 - 1_f32;
+        let nz = (1_f32 - nx * nx - ny * ny).max(0_f32).sqrt();
+        *x = [red[i], green[i], ((nz + 1_f32) * 127.5) as u8, 255];
+      }
+      out
+    }
+  }
+}
+
+fn decode_blocks(format: BlockFormat, width: u32, height: u32, data: &[u8]) -> Option<Image> {
+  let blocks_x = width.div_ceil(4) as usize;
+  let blocks_y = height.div_ceil(4) as usize;
+  let size = format.block_size();
+  if data.len() < blocks_x.checked_mul(blocks_y)?.checked_mul(size)? {
+    return None;
+  }
+  let mut image = Image::new(width, height);
+  for by in 0..blocks_y {
+    for bx in 0..blocks_x {
+      let offset = (by * blocks_x + bx) * size;
+      let texels = decode_block(format, &data[offset..offset + size]);
+      for (i, texel) in texels.iter().enumerate() {
+        let x = (bx * 4 + i % 4) as u32;
+        let y = (by * 4 + i / 4) as u32;
+        if x < width && y < height {
+          image.set_pixel(x, y, *texel);
+        }
+      }
+    }
+  }
+  Some(image)
+}
+
+fn mask_channel(value: u32, mask: u32) -> Option<u8> {
+  if mask == 0 {
+    return None;
+  }
+  let shift = mask.trailing_zeros();
+  let max = mask >> shift;
+  Some((((value & mask) >> shift) * 255 / max) as u8)
+}
+
+fn decode_masked(
+  width: u32,
+  height: u32,
+  bit_count: u32,
+  masks: [u32; 4],
+  data: &[u8],
+) -> Option<Image> {
+  let bytes = (bit_count / 8) as usize;
+  let count = (width as usize).checked_mul(height as usize)?;
+  if bytes == 0 || bytes > 4 || data.len() < count.checked_mul(bytes)? {
+    return None;
+  }
+  let mut image = Image::new(width, height);
+  for (i, texel) in data.chunks_exact(bytes).take(count).enumerate() {
+    let mut value = 0_u32;
+    for (k, x) in texel.iter().enumerate() {
+      value |= (*x as u32) << (k * 8);
+    }
+    let r = mask_channel(value, masks[0]).unwrap_or(0);
+    let g = mask_channel(value, masks[1]).unwrap_or(r);
+    let b = mask_channel(value, masks[2]).unwrap_or(r);
+    let a = mask_channel(value, masks[3]).unwrap_or(255);
+    image.pixels[i * 4..i * 4 + 4].copy_from_slice(&[r, g, b, a]);
+  }
+  Some(image)
+}
+
+// Top mip level of BC1/BC2/BC3/BC5 or uncompressed mask-described textures,
+// including files written with the DX10 extended header.
+pub fn decode_dds(bytes: &[u8]) -> Result<Image, XpsError> {
+  if bytes.len() < 128 || &bytes[0..4] != DDS_MAGIC {
+    return Err(XpsError::TextureNotDecoded);
+  }
+  let height = LittleEndian::read_u32(&bytes[12..16]);
+  let width = LittleEndian::read_u32(&bytes[16..20]);
+  let flags = LittleEndian::read_u32(&bytes[80..84]);
+  let four_cc = &bytes[84..88];
+  let bit_count = LittleEndian::read_u32(&bytes[88..92]);
+  let mut masks = [0_u32; 4];
+  for (i, x) in masks.iter_mut().enumerate() {
+    *x = LittleEndian::read_u32(&bytes[92 + i * 4..96 + i * 4]);
+  }
+  if flags & DDPF_ALPHAPIXELS == 0 {
+    masks[3] = 0;
+  }
+  if width == 0 || height == 0 {
+    return Err(XpsError::TextureNotDecoded);
+  }
+
+  let mut offset = 128;
+  let image = if flags & DDPF_FOURCC != 0 {
+    let format = match four_cc {
+      b"DXT1" => Some(BlockFormat::Bc1),
+      b"DXT2" | b"DXT3" => Some(BlockFormat::Bc2),
+      b"DXT4" | b"DXT5" => Some(BlockFormat::Bc3),
+      b"ATI2" | b"BC5U" => Some(BlockFormat::Bc5),
+      b"DX10" if bytes.len() >= 148 => {
+        offset += 20;
+        match LittleEndian::read_u32(&bytes[128..132]) {
+          70..=72 => Some(BlockFormat::Bc1),
+          73..=75 => Some(BlockFormat::Bc2),
+          76..=78 => Some(BlockFormat::Bc3),
+          82..=84 => Some(BlockFormat::Bc5),
+          _ => None,
+        }
+      }
+      _ => None,
+    };
+    format.and_then(|x| decode_blocks(x, width, height, &bytes[offset..]))
+  } else {
+    decode_masked(width, height, bit_count, masks, &bytes[offset..])
+  };
+  image.ok_or(XpsError::TextureNotDecoded)
+}
+
+// TGA
+
+fn tga_pixel(texel: &[u8]) -> [u8; 4] {
+  match texel.len() {
+    1 => [texel[0], texel[0], texel[0], 255],
+    2 => {
+      let c = LittleEndian::read_u16(texel);
+      let r = ((c >> 10) & 0x1f) as u32 * 255 / 31;
+      let g = ((c >> 5) & 0x1f) as u32 * 255 / 31;
+      let b = (c & 0x1f) as u32 * 255 / 31;
+      [r as u8, g as u8, b as u8, 255]
+    }
+    3 => [texel[2], texel[1], texel[0], 255],
+    _ => [texel[2], texel[1], texel[0], texel[3]],
+  }
+}
+
+// Uncompressed and RLE true-colour or greyscale images.
+pub fn decode_tga(bytes: &[u8]) -> Result<Image, XpsError> {
+  if bytes.len() < 18 {
+    return Err(XpsError::TextureNotDecoded);
+  }
+  let id_length = bytes[0] as usize;
+  let image_type = bytes[2];
+  let width = LittleEndian::read_u16(&bytes[12..14]) as u32;
+  let height = LittleEndian::read_u16(&bytes[14..16]) as u32;
+  let bytes_per_pixel = (bytes[16] as usize).div_ceil(8);
+  let top_down = bytes[17] & 0x20 != 0;
+  if bytes[1] != 0
+    || ![2, 3, 10, 11].contains(&image_type)
+    || !(1..=4).contains(&bytes_per_pixel)
+    || width == 0
+    || height == 0
+  {
+    return Err(XpsError::TextureNotDecoded);
+  }
+
+  // Reject headers whose dimensions the payload cannot cover before
+  // allocating: raw images need every texel, RLE packets expand to at most
+  // 128 texels each.
+  let count = width as usize * height as usize;
+  let mut cursor = 18 + id_length;
+  let payload = bytes.len().saturating_sub(cursor);
+  let required = if image_type < 8 {
+    count * bytes_per_pixel
+  } else {
+    count.div_ceil(128) * (1 + bytes_per_pixel)
+  };
+  if payload < required {
+    return Err(XpsError::TextureNotDecoded);
+  }
+  let mut texels: Vec<[u8; 4]> = Vec::with_capacity(count.min(payload));
+  let next = |cursor: &mut usize| -> Option<[u8; 4]> {
+    let texel = bytes.get(*cursor..*cursor + bytes_per_pixel)?;
+    *cursor += bytes_per_pixel;
+    Some(tga_pixel(texel))
+  };
+  if image_type < 8 {
+    while texels.len() < count {
+      texels.push(next(&mut cursor).ok_or(XpsError::TextureNotDecoded)?);
+    }
+  } else {
+    while texels.len() < count {
+      let packet = *bytes.get(cursor).ok_or(XpsError::TextureNotDecoded)?;
+      cursor += 1;
+      let run = (packet & 0x7f) as usize + 1;
+      if packet & 0x80 != 0 {
+        let texel = next(&mut cursor).ok_or(XpsError::TextureNotDecoded)?;
+        texels.extend(std::iter::repeat_n(texel, run));
+      } else {
+        for _ in 0..run {
+          texels.push(next(&mut cursor).ok_or(XpsError::TextureNotDecoded)?);
+        }
+      }
+    }
+  }
+
+  let mut image = Image::new(width, height);
+  for (i, texel) in texels.iter().take(count).enumerate() {
+    let x = i as u32 % width;
+    let y = i as u32 / width;
+    let y = if top_down { y } else { height - 1 - y };
+    image.set_pixel(x, y, *texel);
+  }
+  Ok(image)
+}
+
+// PNG
+
+const PNG_SIGNATURE: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+  let p = a as i16 + b as i16 - c as i16;
+  let pa = (p - a as i16).abs();
+  let pb = (p - b as i16).abs();
+  let pc = (p - c as i16).abs();
+  if pa <= pb && pa <= pc {
+    a
+  } else if pb <= pc {
+    b
+  } else {
+    c
+  }
+}
+
+fn unfilter(raw: &[u8], stride: usize, bpp: usize, rows: usize) -> Option<Vec<u8>> {
+  if raw.len() < stride.checked_add(1)?.checked_mul(rows)? {
+    return None;
+  }
+  let mut out = vec![0_u8; stride * rows];
+  for y in 0..rows {
+    let line = raw.get(y * (stride + 1)..(y + 1) * (stride + 1))?;
+    let (before, current) = out.split_at_mut(y * stride);
+    let previous = if y > 0 {
+      &before[(y - 1) * stride..]
+    } else {
+      &[][..]
+    };
+    let current = &mut current[..stride];
+    for x in 0..stride {
+      let a = if x >= bpp { current[x - bpp] } else { 0 };
+      let b = previous.get(x).cloned().unwrap_or(0);
+      let c = if x >= bpp {
+        previous.get(x - bpp).cloned().unwrap_or(0)
+      } else {
+        0
+      };
+      let value = line[x + 1];
+      current[x] = match line[0] {
+        0 => value,
+        1 => value.wrapping_add(a),
+        2 => value.wrapping_add(b),
+        3 => value.wrapping_add(((a as u16 + b as u16) / 2) as u8),
+        4 => value.wrapping_add(paeth(a, b, c)),
+        _ => return None,
+      };
+    }
+  }
+  Some(out)
+}
+
+// Non-interlaced 8 and 16 bit images of every colour type, and 8 bit palettes.
+pub fn decode_png(bytes: &[u8]) -> Result<Image, XpsError> {
+  if bytes.len() < 8 || &bytes[0..8] != PNG_SIGNATURE {
+    return Err(XpsError::TextureNotDecoded);
+  }
+  let mut header = None;
+  let mut palette: Vec<[u8; 4]> = vec![];
+  let mut compressed = vec![];
+  let mut cursor = 8;
+  while cursor + 8 <= bytes.len() {
+    let length = byteorder::BigEndian::read_u32(&bytes[cursor..cursor + 4]) as usize;
+    let kind = &bytes[cursor + 4..cursor + 8];
+    let body = bytes
+      .get(cursor + 8..cursor + 8 + length)
+      .ok_or(XpsError::TextureNotDecoded)?;
+    match kind {
+      b"IHDR" if length >= 13 => header = Some(body.to_vec()),
+      b"PLTE" => {
+        palette = body
+          .chunks_exact(3)
+          .map(|x| [x[0], x[1], x[2], 255])
+          .collect()
+      }
+      b"tRNS" => {
+        for (entry, alpha) in palette.iter_mut().zip(body.iter()) {
+          entry[3] = *alpha;
+        }
+      }
+      b"IDAT" => compressed.extend_from_slice(body),
+      b"IEND" => break,
+      _ => {}
+    }
+    cursor += 12 + length;
+  }
+
+  let header = header.ok_or(XpsError::TextureNotDecoded)?;
+  let width = byteorder::BigEndian::read_u32(&header[0..4]);
+  let height = byteorder::BigEndian::read_u32(&header[4..8]);
+  let depth = header[8] as usize;
+  let color_type = header[9];
+  let channels = match color_type {
+    0 | 3 => 1,
+    2 => 3,
+    4 => 2,
+    6 => 4,
+    _ => return Err(XpsError::TextureNotDecoded),
+  };
+  if header[12] != 0
+    || !(depth == 8 || (depth == 16 && color_type != 3))
+    || width == 0
+    || height == 0
+  {
+    return Err(XpsError::TextureNotDecoded);
+  }
+
+  // Every row is a filter byte plus `stride` bytes. Sizes that overflow or
+  // that the decompressed data cannot cover are rejected before allocating
+  // anything of that size, and decompression stops once enough is read.
+  let bpp = channels * depth / 8;
+  let rows = height as usize;
+  let stride = (width as usize)
+    .checked_mul(bpp)
+    .ok_or(XpsError::TextureNotDecoded)?;
+  let required = stride
+    .checked_add(1)
+    .and_then(|x| x.checked_mul(rows))
+    .ok_or(XpsError::TextureNotDecoded)?;
+  let mut raw = vec![];
+  ZlibDecoder::new(&compressed[..])
+    .take(required as u64)
+    .read_to_end(&mut raw)
+    .map_err(|_| XpsError::TextureNotDecoded)?;
+  if raw.len() < required {
+    return Err(XpsError::TextureNotDecoded);
+  }
+  let data = unfilter(&raw, stride, bpp, rows).ok_or(XpsError::TextureNotDecoded)?;
+
+  let mut image = Image::new(width, height);
+  let sample_size = depth / 8;
+  for (i, texel) in data.chunks_exact(bpp).enumerate() {
+    let s = |k: usize| texel[k * sample_size];
+    let value = match color_type {
+      0 => [s(0), s(0), s(0), 255],
+      2 => [s(0), s(1), s(2), 255],
+      3 => *palette
+        .get(s(0) as usize)
+        .ok_or(XpsError::TextureNotDecoded)?,
+      4 => [s(0), s(0), s(0), s(1)],
+      _ => [s(0), s(1), s(2), s(3)],
+    };
+    image.pixels[i * 4..i * 4 + 4].copy_from_slice(&value);
+  }
+  Ok(image)
+}
+
+fn write_chunk(out: &mut Vec<u8>, kind: &[u8], body: &[u8]) {
+  let mut length = [0_u8; 4];
+  byteorder::BigEndian::write_u32(&mut length, body.len() as u32);
+  out.extend_from_slice(&length);
+  let mut crc = Crc::new();
+  crc.update(kind);
+  crc.update(body);
+  out.extend_from_slice(kind);
+  out.extend_from_slice(body);
+  let mut checksum = [0_u8; 4];
+  byteorder::BigEndian::write_u32(&mut checksum, crc.sum());
+  out.extend_from_slice(&checksum);
+}
+
+pub fn encode_png(image: &Image) -> Vec<u8> {
+  let mut header = [0_u8; 13];
+  byteorder::BigEndian::write_u32(&mut header[0..4], image.width);
+  byteorder::BigEndian::write_u32(&mut header[4..8], image.height);
+  header[8] = 8;
+  header[9] = 6;
+
+  // Up filter on every row; cheap and compresses texture data well.
+  let stride = image.width as usize * 4;
+  let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+  for y in 0..image.height as usize {
+    let row = &image.pixels[y * stride..(y + 1) * stride];
+    let mut line = Vec::with_capacity(stride + 1);
+    line.push(2_u8);
+    if y == 0 {
+      line.extend_from_slice(row);
+    } else {
+      let previous = &image.pixels[(y - 1) * stride..y * stride];
+      line.extend(
+        row
+          .iter()
+          .zip(previous.iter())
+          .map(|x| x.0.wrapping_sub(*x.1)),
+      );
+    }
+    let _ = encoder.write_all(&line);
+  }
+  let compressed = encoder.finish().unwrap_or_default();
+
+  let mut out = PNG_SIGNATURE.to_vec();
+  write_chunk(&mut out, b"IHDR", &header);
+  write_chunk(&mut out, b"IDAT", &compressed);
+  write_chunk(&mut out, b"IEND", &[]);
+  out
+}
+
+// Picks the decoder from the file contents, falling back to TGA which has no
+// signature.
+pub fn decode(bytes: &[u8]) -> Result<Image, XpsError> {
+  if bytes.starts_with(DDS_MAGIC) {
+    decode_dds(bytes)
+  } else if bytes.starts_with(PNG_SIGNATURE) {
+    decode_png(bytes)
+  } else {
+    decode_tga(bytes)
+  }
+}
+
+pub fn load_image<P: AsRef<Path>>(filename: P) -> Result<Image, XpsError> {
+  let bytes = std::fs::read(filename).map_err(|_| XpsError::FileNotLoaded)?;
+  decode(&bytes)
+}
+
+pub fn write_png<P: AsRef<Path>>(image: &Image, filename: P) -> Result<(), XpsError> {
+  std::fs::write(filename, encode_png(image)).map_err(|_| XpsError::FileNotWritten)
+}
+
+// KTX2
+
+const KTX2_IDENTIFIER: &[u8] = &[
+  0xab, b'K', b'T', b'X', b' ', b'2', b'0', 0xbb, 0x0d, 0x0a, 0x1a, 0x0a,
+];
+const VK_FORMAT_R8G8B8A8_UNORM: u32 = 37;
+const VK_FORMAT_R8G8B8A8_SRGB: u32 = 43;
+const KTX2_HEADER_SIZE: usize = 80;
+const KTX2_LEVEL_INDEX_SIZE: usize = 24;
+// Basic data format descriptor block with one sample per RGBA channel.
+const KTX2_DFD_BLOCK_SIZE: usize = 24 + 16 * 4;
+
+fn push_u32(out: &mut Vec<u8>, value: u32) {
+  let mut bytes = [0_u8; 4];
+  LittleEndian::write_u32(&mut bytes, value);
+  out.extend_from_slice(&bytes);
+}
+
+fn push_u64(out: &mut Vec<u8>, value: u64) {
+  let mut bytes = [0_u8; 8];
+  LittleEndian::write_u64(&mut bytes, value);
+  out.extend_from_slice(&bytes);
+}
+
+// Single level, uncompressed RGBA8 without supercompression. Colour
+// textures should be written as sRGB, data such as normal or
+// metallic-roughness maps as linear.
+pub fn encode_ktx2(image: &Image, srgb: bool) -> Vec<u8> {
+  let dfd_offset = KTX2_HEADER_SIZE + KTX2_LEVEL_INDEX_SIZE;
+  let dfd_length = 4 + KTX2_DFD_BLOCK_SIZE;
+  let level_offset = dfd_offset + dfd_length;
+  let mut out = Vec::with_capacity(level_offset + image.pixels.len());
+  out.extend_from_slice(KTX2_IDENTIFIER);
+  push_u32(
+    &mut out,
+    if srgb {
+      VK_FORMAT_R8G8B8A8_SRGB
+    } else {
+      VK_FORMAT_R8G8B8A8_UNORM
+    },
+  );
+  // Type size, width, height, depth, layers, faces, levels, supercompression.
+  for value in [1, image.width, image.height, 0, 0, 1, 1, 0].iter() {
+    push_u32(&mut out, *value);
+  }
+  push_u32(&mut out, dfd_offset as u32);
+  push_u32(&mut out, dfd_length as u32);
+  // No key/value data and no supercompression global data.
+  push_u32(&mut out, 0);
+  push_u32(&mut out, 0);
+  push_u64(&mut out, 0);
+  push_u64(&mut out, 0);
+
+  push_u64(&mut out, level_offset as u64);
+  push_u64(&mut out, image.pixels.len() as u64);
+  push_u64(&mut out, image.pixels.len() as u64);
+
+  push_u32(&mut out, dfd_length as u32);
+  // Vendor Khronos, basic descriptor type, version 2.
+  push_u32(&mut out, 0);
+  push_u32(&mut out, 2 | (KTX2_DFD_BLOCK_SIZE as u32) << 16);
+  // RGBSDA colour model, BT.709 primaries, transfer function, straight alpha.
+  out.extend_from_slice(&[1, 1, if srgb { 2 } else { 1 }, 0]);
+  out.extend_from_slice(&[0; 4]);
+  out.extend_from_slice(&[4, 0, 0, 0, 0, 0, 0, 0]);
+  for (index, channel) in [0_u8, 1, 2, 15].iter().enumerate() {
+    // Alpha stays linear in sRGB textures.
+    let qualifiers = if *channel == 15 && srgb { 0x10 } else { 0 };
+    push_u32(
+      &mut out,
+      (index as u32 * 8) | 7 << 16 | ((channel | qualifiers) as u32) << 24,
+    );
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 0);
+    push_u32(&mut out, 255);
+  }
+  out.extend_from_slice(&image.pixels);
+  out
+}
+
+pub fn write_ktx2<P: AsRef<Path>>(image: &Image, srgb: bool, filename: P) -> Result<(), XpsError> {
+  std::fs::write(filename, encode_ktx2(image, srgb)).map_err(|_| XpsError::FileNotWritten)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputFormat {
+  Png,
+  Ktx2,
+}
+
+impl OutputFormat {
+  pub fn extension(self) -> &'static str {
+    match self {
+      OutputFormat::Png => "png",
+      OutputFormat::Ktx2 => "ktx2",
+    }
+  }
+
+  // PNG has no colour space flag, so `srgb` only affects KTX2.
+  pub fn write<P: AsRef<Path>>(
+    self,
+    image: &Image,
+    srgb: bool,
+    filename: P,
+  ) -> Result<(), XpsError> {
+    match self {
+      OutputFormat::Png => write_png(image, filename),
+      OutputFormat::Ktx2 => write_ktx2(image, srgb, filename),
+    }
+  }
+}
+
+// Channel packing
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Channel {
+  Red,
+  Green,
+  Blue,
+  Alpha,
+  Luminance,
+}
+
+impl Channel {
+  fn read(self, rgba: [u8; 4]) -> u8 {
+    match self {
+      Channel::Red => rgba[0],
+      Channel::Green => rgba[1],
+      Channel::Blue => rgba[2],
+      Channel::Alpha => rgba[3],
+      Channel::Luminance => {
+        ((rgba[0] as u32 * 54 + rgba[1] as u32 * 183 + rgba[2] as u32 * 19) >> 8) as u8
+      }
+    }
+  }
+}
+
+#[derive(Clone, Copy)]
+pub enum ChannelSource<'a> {
+  Constant(u8),
+  Image(&'a Image, Channel),
+  Inverted(&'a Image, Channel),
+}
+
+// Builds an RGBA image whose channels are taken from other images or
+// constants. The output takes the size of the largest source image.
+pub fn pack_channels(sources: [ChannelSource; 4]) -> Image {
+  let (width, height) = sources
+    .iter()
+    .filter_map(|x| match x {
+      ChannelSource::Image(image, _) | ChannelSource::Inverted(image, _) => {
+        Some((image.width, image.height))
+      }
+      ChannelSource::Constant(_) => None,
+    })
+    .max_by_key(|x| x.0 as u64 * x.1 as u64)
+    .unwrap_or((1, 1));
+  let mut out = Image::new(width, height);
+  for y in 0..height {
+    for x in 0..width {
+      let mut value = [0_u8; 4];
+      for (channel, source) in value.iter_mut().zip(sources.iter()) {
+        *channel = match source {
+          ChannelSource::Constant(c) => *c,
+          ChannelSource::Image(image, c) => c.read(image.sample(x, y, width, height)),
+          ChannelSource::Inverted(image, c) => 255 - c.read(image.sample(x, y, width, height)),
+        };
+      }
+      out.set_pixel(x, y, value);
+    }
+  }
+  out
+}
+
+// glTF layout: roughness in green, metallic in blue. XPS specular maps are
+// treated as gloss, so roughness is the inverted luminance; XPS has no
+// metalness, so it is set to zero.
+pub fn metallic_roughness_from_specular(specular: &Image) -> Image {
+  pack_channels([
+    ChannelSource::Constant(255),
+    ChannelSource::Inverted(specular, Channel::Luminance),
+    ChannelSource::Constant(0),
+    ChannelSource::Constant(255),
+  ])
+}
+
+// Model conversion
+
+pub struct ConvertedTexture {
+  pub mesh_index: usize,
+  pub texture_index: usize,
+  pub source: PathBuf,
+  pub output: PathBuf,
+}
+
+pub struct TextureConversion {
+  pub textures: Vec<ConvertedTexture>,
+  pub metallic_roughness: Vec<ConvertedTexture>,
+  pub failed: Vec<(usize, usize)>,
+}
+
+// Sources with the same stem in different directories or with different
+// extensions get a numbered name instead of overwriting each other.
+fn output_name(source: &Path, suffix: &str, extension: &str, used: &mut HashSet<String>) -> String {
+  let stem = source
+    .file_stem()
+    .and_then(|x| x.to_str())
+    .unwrap_or("texture");
+  let mut name = format!("{}{}.{}", stem, suffix, extension);
+  let mut number = 1;
+  while !used.insert(name.to_lowercase()) {
+    number += 1;
+    name = format!("{}_{}{}.{}", stem, number, suffix, extension);
+  }
+  name
+}
+
+// Decodes every texture the model references and writes it as `format`
+// into `output_directory`, plus a metallic-roughness map for each specular
+// map. Base colour, emission and environment maps are colour data, the rest
+// are linear. Textures shared between meshes are converted once per colour
+// space. Textures that cannot be found or decoded are listed in `failed` as
+// (mesh, texture) indices.
+pub fn convert_model_textures<P: AsRef<Path>>(
+  data: &Data,
+  resolver: &TextureResolver,
+  output_directory: P,
+  format: OutputFormat,
+) -> Result<TextureConversion, XpsError> {
+  let output_directory = output_directory.as_ref();
+  std::fs::create_dir_all(output_directory).map_err(|_| XpsError::FileNotWritten)?;
+  let mut result = TextureConversion {
+    textures: vec![],
+    metallic_roughness: vec![],
+    failed: vec![],
+  };
+  let mut written: HashMap<(PathBuf, &str, bool), PathBuf> = HashMap::new();
+  let mut used = HashSet::new();
+
+  for (mesh_index, mesh) in data.meshes.iter().enumerate() {
+    let material = mesh.pbr_material();
+    let specular = material.specular.map(|x| x.texture_index);
+    let color: Vec<usize> = [material.base_color, material.emission, material.environment]
+      .iter()
+      .flatten()
+      .map(|x| x.texture_index)
+      .collect();
+    for (texture_index, texture) in mesh.textures.iter().enumerate() {
+      let source = match resolver.resolve(texture) {
+        Some(x) => x,
+        None => {
+          result.failed.push((mesh_index, texture_index));
+          continue;
+        }
+      };
+      let mut outputs = vec![("", &mut result.textures)];
+      if specular == Some(texture_index) {
+        outputs.push(("_metallic_roughness", &mut result.metallic_roughness));
+      }
+      let mut image = None;
+      for (suffix, list) in outputs {
+        let srgb =
+          format == OutputFormat::Ktx2 && suffix.is_empty() && color.contains(&texture_index);
+        let output = match written.get(&(source.clone(), suffix, srgb)) {
+          Some(x) => x.clone(),
+          None => {
+            if image.is_none() {
+              match load_image(&source) {
+                Ok(x) => image = Some(x),
+                Err(_) => {
+                  result.failed.push((mesh_index, texture_index));
+                  break;
+                }
+              }
+            }
+            let decoded = image.as_ref().unwrap();
+            let name = output_name(&source, suffix, format.extension(), &mut used);
+            let output = output_directory.join(name);
+            if suffix.is_empty() {
+              format.write(decoded, srgb, &output)?;
+            } else {
+              format.write(&metallic_roughness_from_specular(decoded), srgb, &output)?;
+            }
+            written.insert((source.clone(), suffix, srgb), output.clone());
+            output
+          }
+        };
+        list.push(ConvertedTexture {
+          mesh_index,
+          texture_index,
+          source: source.clone(),
+          output,
+        });
+      }
+    }
+  }
+  Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn tga_header(image_type: u8, width: u16, height: u16) -> Vec<u8> {
+    let mut bytes = vec![0_u8; 18];
+    bytes[2] = image_type;
+    LittleEndian::write_u16(&mut bytes[12..14], width);
+    LittleEndian::write_u16(&mut bytes[14..16], height);
+    bytes[16] = 32;
+    bytes[17] = 0x20;
+    bytes
+  }
+
+  #[test]
+  fn tga_decodes_raw_and_rle() {
+    let mut raw = tga_header(2, 2, 1);
+    raw.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+    let image = decode_tga(&raw).unwrap();
+    assert_eq!(image.pixel(0, 0), [3, 2, 1, 4]);
+    assert_eq!(image.pixel(1, 0), [7, 6, 5, 8]);
+
+    let mut rle = tga_header(10, 3, 1);
+    rle.extend_from_slice(&[0x82, 1, 2, 3, 4]);
+    let image = decode_tga(&rle).unwrap();
+    assert_eq!(image.pixel(2, 0), [3, 2, 1, 4]);
+  }
+
+  #[test]
+  fn tga_rejects_dimensions_larger_than_payload() {
+    let mut raw = tga_header(2, u16::MAX, u16::MAX);
+    raw.extend_from_slice(&[0; 64]);
+    assert!(decode_tga(&raw).is_err());
+    let mut rle = tga_header(10, u16::MAX, u16::MAX);
+    rle.extend_from_slice(&[0xff, 0, 0, 0, 0]);
+    assert!(decode_tga(&rle).is_err());
+  }
+
+  fn png_bytes(width: u32, height: u32, depth: u8, color_type: u8, raw: &[u8]) -> Vec<u8> {
+    let mut header = [0_u8; 13];
+    byteorder::BigEndian::write_u32(&mut header[0..4], width);
+    byteorder::BigEndian::write_u32(&mut header[4..8], height);
+    header[8] = depth;
+    header[9] = color_type;
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(raw).unwrap();
+    let mut out = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut out, b"IHDR", &header);
+    write_chunk(&mut out, b"IDAT", &encoder.finish().unwrap());
+    write_chunk(&mut out, b"IEND", &[]);
+    out
+  }
+
+  #[test]
+  fn png_round_trips_and_decodes_16_bit() {
+    let mut image = Image::new(2, 2);
+    image.set_pixel(1, 0, [10, 20, 30, 40]);
+    image.set_pixel(0, 1, [50, 60, 70, 80]);
+    let decoded = decode_png(&encode_png(&image)).unwrap();
+    assert_eq!(decoded.pixels, image.pixels);
+
+    let raw = [0, 1, 2, 3, 4, 5, 6, 7, 8];
+    let decoded = decode_png(&png_bytes(1, 1, 16, 6, &raw)).unwrap();
+    assert_eq!(decoded.pixel(0, 0), [1, 3, 5, 7]);
+  }
+
+  #[test]
+  fn png_rejects_dimensions_larger_than_payload() {
+    let raw = [0_u8; 64];
+    assert!(decode_png(&png_bytes(u32::MAX, u32::MAX, 16, 6, &raw)).is_err());
+    assert!(decode_png(&png_bytes(u32::MAX, 1, 16, 6, &raw)).is_err());
+    assert!(decode_png(&png_bytes(65535, 65535, 8, 6, &raw)).is_err());
+    assert!(decode_png(&png_bytes(2, 2, 8, 6, &raw[..9])).is_err());
+    assert!(decode_png(&png_bytes(0, 2, 8, 6, &raw)).is_err());
+  }
+
+  #[test]
+  fn dds_rejects_dimensions_larger_than_payload() {
+    let mut bytes = vec![0_u8; 128 + 64];
+    bytes[0..4].copy_from_slice(DDS_MAGIC);
+    LittleEndian::write_u32(&mut bytes[12..16], u32::MAX);
+    LittleEndian::write_u32(&mut bytes[16..20], u32::MAX);
+    LittleEndian::write_u32(&mut bytes[80..84], DDPF_FOURCC);
+    bytes[84..88].copy_from_slice(b"DXT1");
+    assert!(decode_dds(&bytes).is_err());
+    LittleEndian::write_u32(&mut bytes[80..84], 0);
+    LittleEndian::write_u32(&mut bytes[88..92], 32);
+    LittleEndian::write_u32(&mut bytes[92..96], 0xff);
+    assert!(decode_dds(&bytes).is_err());
+  }
+
+  #[test]
+  fn ktx2_stores_uncompressed_rgba() {
+    let mut image = Image::new(2, 1);
+    image.set_pixel(1, 0, [10, 20, 30, 40]);
+    let bytes = encode_ktx2(&image, true);
+    let u32_at = |offset: usize| LittleEndian::read_u32(&bytes[offset..offset + 4]);
+    let u64_at = |offset: usize| LittleEndian::read_u64(&bytes[offset..offset + 8]);
+    assert_eq!(&bytes[0..12], KTX2_IDENTIFIER);
+    assert_eq!(u32_at(12), VK_FORMAT_R8G8B8A8_SRGB);
+    assert_eq!((u32_at(20), u32_at(24), u32_at(36)), (2, 1, 1));
+    let dfd = u32_at(48) as usize;
+    assert_eq!(u32_at(dfd), u32_at(52));
+    assert_eq!(bytes[dfd + 12..dfd + 15], [1, 1, 2]);
+    let level = u64_at(80) as usize;
+    assert_eq!(level, dfd + u32_at(52) as usize);
+    assert_eq!(level % 4, 0);
+    assert_eq!(u64_at(88), 8);
+    assert_eq!(&bytes[level..], &image.pixels[..]);
+    assert_eq!(bytes.len(), level + 8);
+
+    let linear = encode_ktx2(&image, false);
+    assert_eq!(
+      LittleEndian::read_u32(&linear[12..16]),
+      VK_FORMAT_R8G8B8A8_UNORM
+    );
+    assert_eq!(linear[dfd + 14], 1);
+  }
+
+  #[test]
+  fn output_names_are_unique_per_source() {
+    let mut used = HashSet::new();
+    let a = output_name(Path::new("a/skin.dds"), "", "png", &mut used);
+    let b = output_name(Path::new("b/skin.tga"), "", "png", &mut used);
+    let c = output_name(
+      Path::new("b/skin.tga"),
+      "_metallic_roughness",
+      "ktx2",
+      &mut used,
+    );
+    assert_eq!(a, "skin.png");
+    assert_eq!(b, "skin_2.png");
+    assert_eq!(c, "skin_metallic_roughness.ktx2");
+  }
+}
@@ -0,0 +1,190 @@
+use super::types::{Data, Mesh, Texture};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const ALTERNATE_EXTENSIONS: [&str; 3] = ["dds", "png", "tga"];
+
+// Last component of a texture path written on any platform.
+pub fn file_name(path: &str) -> &str {
+  path.rsplit(['\\', '/']).next().unwrap_or(path)
+}
+
+// Relative texture paths as written in the file, with either separator.
+// Absolute paths and drive letters point at the author's machine and are
+// ignored.
+fn relative_path(path: &str) -> Option<PathBuf> {
+  let path = path.replace('\\', "/");
+  if path.starts_with('/') || path.contains(':') {
+    return None;
+  }
+  let relative: PathBuf = path
+    .split('/')
+    .filter(|x| !x.is_empty() && *x != ".")
+    .collect();
+  if relative.as_os_str().is_empty() {
+    None
+  } else {
+    Some(relative)
+  }
+}
+
+fn stem(name: &str) -> &str {
+  match name.rfind('.') {
+    Some(x) if x > 0 => &name[..x],
+    _ => name,
+  }
+}
+
+pub struct MeshTextureReport {
+  pub mesh_index: usize,
+  pub resolved: Vec<Option<PathBuf>>,
+}
+
+impl MeshTextureReport {
+  pub fn missing(&self) -> Vec<usize> {
+    self
+      .resolved
+      .iter()
+      .enumerate()
+      .filter(|x| x.1.is_none())
+      .map(|x| x.0)
+      .collect()
+  }
+}
+
+// Looks textures up in the model's directory and any extra search roots, in
+// that order. A relative path from the file is tried as written under each
+// root first; after that only the file name is used. File name matching
+// ignores case, and a texture that is not found as named is retried with
+// each of `ALTERNATE_EXTENSIONS`.
+pub struct TextureResolver {
+  roots: Vec<PathBuf>,
+  listings: Vec<HashMap<String, PathBuf>>,
+}
+
+impl TextureResolver {
+  pub fn new(model_filename: &str) -> TextureResolver {
+    let mut resolver = TextureResolver {
+      roots: vec![],
+      listings: vec![],
+    };
+    let directory = match Path::new(model_filename).parent() {
+      Some(x) if !x.as_os_str().is_empty() => x.to_path_buf(),
+      _ => PathBuf::from("."),
+    };
+    resolver.add_search_root(directory);
+    resolver
+  }
+
+  pub fn add_search_root<P: AsRef<Path>>(&mut self, root: P) {
+    let root = root.as_ref().to_path_buf();
+    if self.roots.contains(&root) {
+      return;
+    }
+    let mut listing = HashMap::new();
+    if let Ok(entries) = std::fs::read_dir(&root) {
+      for entry in entries.flatten() {
+        if !entry.path().is_file() {
+          continue;
+        }
+        if let Some(x) = entry.file_name().to_str() {
+          listing.insert(x.to_lowercase(), entry.path());
+        }
+      }
+    }
+    self.roots.push(root);
+    self.listings.push(listing);
+  }
+
+  pub fn search_roots(&self) -> &[PathBuf] {
+    &self.roots
+  }
+
+  fn find(&self, name: &str) -> Option<PathBuf> {
+    let name = name.to_lowercase();
+    self.listings.iter().find_map(|x| x.get(&name).cloned())
+  }
+
+  pub fn resolve_name(&self, name: &str) -> Option<PathBuf> {
+    if let Some(relative) = relative_path(name) {
+      let found = self
+        .roots
+        .iter()
+        .map(|x| x.join(&relative))
+        .find(|x| x.is_file());
+      if found.is_some() {
+        return found;
+      }
+    }
+    let name = file_name(name);
+    if name.is_empty() {
+      return None;
+    }
+    self.find(name).or_else(|| {
+      ALTERNATE_EXTENSIONS
+        .iter()
+        .find_map(|x| self.find(&format!("{}.{}", stem(name), x)))
+    })
+  }
+
+  pub fn resolve(&self, texture: &Texture) -> Option<PathBuf> {
+    match texture.path.to_str() {
+      Ok(x) if !x.is_empty() => self.resolve_name(x),
+      _ => self.resolve_name(texture.file.to_str().unwrap_or("")),
+    }
+  }
+
+  pub fn resolve_mesh(&self, mesh: &Mesh, mesh_index: usize) -> MeshTextureReport {
+    MeshTextureReport {
+      mesh_index,
+      resolved: mesh.textures.iter().map(|x| self.resolve(x)).collect(),
+    }
+  }
+
+  pub fn resolve_model(&self, data: &Data) -> Vec<MeshTextureReport> {
+    data
+      .meshes
+      .iter()
+      .enumerate()
+      .map(|x| self.resolve_mesh(x.1, x.0))
+      .collect()
+  }
+
+  // Only the meshes with at least one texture that could not be found.
+  pub fn missing_textures(&self, data: &Data) -> Vec<MeshTextureReport> {
+    self
+      .resolve_model(data)
+      .into_iter()
+      .filter(|x| x.resolved.iter().any(|y| y.is_none()))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn relative_paths_first_then_file_names_ignoring_case() {
+    let root = std::env::temp_dir().join("xpsimport_texture_resolver_test");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("extra")).unwrap();
+    std::fs::create_dir_all(root.join("Sub")).unwrap();
+    for name in ["Body.DDS", "Sub/body.dds", "extra/Skin.PNG"].iter() {
+      std::fs::write(root.join(name), b"").unwrap();
+    }
+    let mut resolver = TextureResolver::new(root.join("model.xps").to_str().unwrap());
+    resolver.add_search_root(root.join("extra"));
+
+    let sub = resolver.resolve_name("Sub\\body.dds");
+    let body = resolver.resolve_name("C:\\xps\\textures\\body.dds");
+    let skin = resolver.resolve_name("textures/skin.dds");
+    let missing = resolver.resolve_name("hair.dds");
+    let _ = std::fs::remove_dir_all(&root);
+
+    assert_eq!(sub, Some(root.join("Sub").join("body.dds")));
+    assert_eq!(body, Some(root.join("Body.DDS")));
+    assert_eq!(skin, Some(root.join("extra").join("Skin.PNG")));
+    assert_eq!(missing, None);
+  }
+}
@@ -1,10 +1,50 @@
-use super::error_types::XpsError;
-use super::material::RenderGroup;
+use super::constants;
+use super::conversion::{Axis, Handedness};
+use super::error_types::{XpsError, XpsWarning};
+use super::material::{RenderGroup, TextureSlot};
+use super::mesh_buffers::MeshBuffers;
+use super::mesh_name_parser::MeshNameParser;
+use super::optional_items::OptionalItem;
 use std::ffi::CString;
 
+#[derive(Clone, Copy)]
 pub struct ImportParameters {
   pub flip_uv: bool,
   pub reverse_winding: bool,
+  pub optimize_vertex_cache: bool,
+  pub optimize_overdraw: bool,
+  pub normalize_weights: bool,
+  pub weight_threshold: f32,
+  pub max_bone_influences: u8,
+  pub orient_bones: bool,
+  pub up_axis: Axis,
+  pub forward_axis: Axis,
+  pub handedness: Handedness,
+  pub scale: f32,
+  // Read vertex data straight into `Mesh::buffers` instead of
+  // `Mesh::vertices`. Batching, welding, simplification and SMD export
+  // unpack the buffers into vertices and pack their results again.
+  pub mesh_buffers: bool,
+}
+
+impl Default for ImportParameters {
+  fn default() -> ImportParameters {
+    ImportParameters {
+      flip_uv: constants::FLIP_UV,
+      reverse_winding: constants::REVERSE_WINDING,
+      optimize_vertex_cache: false,
+      optimize_overdraw: false,
+      normalize_weights: false,
+      weight_threshold: 0_f32,
+      max_bone_influences: 4,
+      orient_bones: false,
+      up_axis: super::conversion::XPS_UP,
+      forward_axis: super::conversion::XPS_FORWARD,
+      handedness: super::conversion::XPS_HANDEDNESS,
+      scale: 1_f32,
+      mesh_buffers: false,
+    }
+  }
 }
 
 pub struct Bone {
@@ -12,6 +52,7 @@ pub struct Bone {
   pub name: CString,
   pub co: [f32; 3],
   pub parent_id: i16,
+  pub rest_rotation: [f32; 4],
 }
 
 pub struct BonePose {
@@ -21,13 +62,108 @@ pub struct BonePose {
   pub scale: [f32; 3],
 }
 
+#[derive(Clone)]
 pub struct Mesh {
   pub name: CString,
+  pub full_name: CString,
   pub textures: Vec<Texture>,
   pub vertices: Vec<Vertex>,
   pub faces: Vec<u32>,
   pub uv_count: u16,
   pub render_group: RenderGroup,
+  pub render_parameters: RenderParameters,
+  pub item: MeshItem,
+  pub camera: Option<CameraTarget>,
+  pub buffers: Option<MeshBuffers>,
+}
+
+// Optional items are the meshes whose name starts with `+` (shown by
+// default) or `-` (hidden by default); meshes sharing an item name are
+// toggled together.
+#[derive(Clone, PartialEq)]
+pub struct MeshItem {
+  pub name: CString,
+  pub optional: bool,
+  pub visible_by_default: bool,
+}
+
+impl MeshItem {
+  pub fn from_parser(parser: &MeshNameParser) -> MeshItem {
+    MeshItem {
+      name: CString::new(parser.item_name.clone()).unwrap_or_default(),
+      optional: parser.has_optional_items,
+      visible_by_default: !parser.has_optional_items || parser.is_visible_by_default,
+    }
+  }
+}
+
+// Camera named at the end of the mesh name and the bones it frames. Bone
+// indices are filled in by the loader; a target without a matching bone is
+// `None`.
+#[derive(Clone, PartialEq)]
+pub struct CameraTarget {
+  pub camera: CString,
+  pub targets: Vec<CString>,
+  pub bone_indices: Vec<Option<usize>>,
+}
+
+impl CameraTarget {
+  pub fn from_parser(parser: &MeshNameParser) -> Option<CameraTarget> {
+    if !parser.has_camera_targets {
+      return None;
+    }
+    let targets: Vec<CString> = parser
+      .camera_targets
+      .iter()
+      .map(|x| CString::new(x.clone()).unwrap_or_default())
+      .collect();
+    Some(CameraTarget {
+      camera: CString::new(parser.camera_name.clone()).unwrap_or_default(),
+      bone_indices: vec![None; targets.len()],
+      targets,
+    })
+  }
+}
+
+// Per-mesh values encoded in the mesh name after the render group.
+#[derive(Clone, Copy, PartialEq)]
+pub struct RenderParameters {
+  pub specular_amount: f32,
+  pub bump1_uv_scale: f32,
+  pub bump2_uv_scale: f32,
+}
+
+impl Default for RenderParameters {
+  fn default() -> RenderParameters {
+    RenderParameters {
+      specular_amount: 0.1,
+      bump1_uv_scale: 1_f32,
+      bump2_uv_scale: 1_f32,
+    }
+  }
+}
+
+impl RenderParameters {
+  pub fn from_parser(parser: &MeshNameParser) -> RenderParameters {
+    let default = RenderParameters::default();
+    RenderParameters {
+      specular_amount: if parser.has_specular_amount {
+        parser.specular_amount
+      } else {
+        default.specular_amount
+      },
+      bump1_uv_scale: if parser.has_bump1_uv_scale {
+        parser.bump1_uv_scale
+      } else {
+        default.bump1_uv_scale
+      },
+      bump2_uv_scale: if parser.has_bump2_uv_scale {
+        parser.bump2_uv_scale
+      } else {
+        default.bump2_uv_scale
+      },
+    }
+  }
 }
 
 #[derive(Default, Copy, Clone)]
@@ -36,20 +172,24 @@ pub struct BoneWeight {
   pub weight: f32,
 }
 
-#[derive(Default, Clone, Copy)]
+#[derive(Default, Clone)]
 pub struct Vertex {
   pub position: [f32; 3],
   pub normal: [f32; 3],
   pub color: [u8; 4],
-  pub uv: [[f32; 2]; 3],
-  pub bone_weights: [BoneWeight; 4],
+  // One entry per UV layer of the mesh.
+  pub uv: Vec<[f32; 2]>,
+  pub bone_weights: Vec<BoneWeight>,
   pub merged: bool,
 }
 
+#[derive(Clone, PartialEq)]
 pub struct Texture {
   pub id: u16,
   pub file: CString,
+  pub path: CString,
   pub uv_layer: u16,
+  pub slot: TextureSlot,
 }
 
 #[derive(Default)]
@@ -57,6 +197,10 @@ pub struct Data {
   pub header: Header,
   pub bones: Vec<Bone>,
   pub meshes: Vec<Mesh>,
+  pub optional_items: Vec<OptionalItem>,
+  pub bone_order: Vec<usize>,
+  pub parameters: ImportParameters,
+  pub warnings: Vec<XpsWarning>,
   pub error: XpsError,
 }
 
@@ -69,10 +213,129 @@ pub struct Header {
   pub machine: String,
   pub user: String,
   pub file: String,
-  pub settings: String,
+  pub settings: HeaderSettings,
+  pub flags: HeaderFlags,
   pub pose: String,
 }
 
+impl Header {
+  // Only files up to version 1.12 store tangents.
+  pub fn has_tangents(&self) -> bool {
+    self.version_mayor <= 1 && self.version_minor <= 12
+  }
+
+  // Each vertex stores its own number of influences instead of always four,
+  // starting with version 3.
+  pub fn has_variable_weights(&self) -> bool {
+    self.version_mayor >= 3
+  }
+}
+
+// Values of the flags option, with XPS's defaults for keys a file leaves
+// out. Tangent space channels are 0 for straight and 1 for inverted.
+#[derive(Clone, PartialEq, Debug)]
+pub struct HeaderFlags {
+  pub backface_culling: bool,
+  pub always_force_culling: bool,
+  pub model_cast_shadows: bool,
+  pub tangent_space_red: u32,
+  pub tangent_space_green: u32,
+  pub tangent_space_blue: u32,
+  pub gloss: u32,
+  pub has_bone_directions: bool,
+  // Pairs with keys this reader does not know, in file order.
+  pub unknown: Vec<(u32, u32)>,
+}
+
+impl Default for HeaderFlags {
+  fn default() -> HeaderFlags {
+    HeaderFlags {
+      backface_culling: false,
+      always_force_culling: false,
+      model_cast_shadows: true,
+      tangent_space_red: 0,
+      tangent_space_green: 1,
+      tangent_space_blue: 0,
+      gloss: 10,
+      has_bone_directions: false,
+      unknown: vec![],
+    }
+  }
+}
+
+impl HeaderFlags {
+  pub fn from_pairs(pairs: &[(u32, u32)]) -> HeaderFlags {
+    let mut flags = HeaderFlags::default();
+    for (key, value) in pairs.iter().cloned() {
+      match key {
+        constants::FLAG_BACKFACE_CULLING => flags.backface_culling = value != 0,
+        constants::FLAG_ALWAYS_FORCE_CULLING => flags.always_force_culling = value != 0,
+        constants::FLAG_MODEL_CAST_SHADOWS => flags.model_cast_shadows = value != 0,
+        constants::FLAG_TANGENT_SPACE_RED => flags.tangent_space_red = value,
+        constants::FLAG_TANGENT_SPACE_GREEN => flags.tangent_space_green = value,
+        constants::FLAG_TANGENT_SPACE_BLUE => flags.tangent_space_blue = value,
+        constants::FLAG_GLOSS => flags.gloss = value,
+        constants::FLAG_HAS_BONE_DIRECTIONS => flags.has_bone_directions = value != 0,
+        _ => flags.unknown.push((key, value)),
+      }
+    }
+    flags
+  }
+
+  pub fn from_settings(settings: &HeaderSettings) -> HeaderFlags {
+    match settings.entry(constants::OPTION_FLAGS).map(|x| &x.value) {
+      Some(SettingsValue::Flags(x)) => HeaderFlags::from_pairs(x),
+      _ => HeaderFlags::default(),
+    }
+  }
+
+  pub fn value(&self, key: u32) -> Option<u32> {
+    match key {
+      constants::FLAG_BACKFACE_CULLING => Some(self.backface_culling as u32),
+      constants::FLAG_ALWAYS_FORCE_CULLING => Some(self.always_force_culling as u32),
+      constants::FLAG_MODEL_CAST_SHADOWS => Some(self.model_cast_shadows as u32),
+      constants::FLAG_TANGENT_SPACE_RED => Some(self.tangent_space_red),
+      constants::FLAG_TANGENT_SPACE_GREEN => Some(self.tangent_space_green),
+      constants::FLAG_TANGENT_SPACE_BLUE => Some(self.tangent_space_blue),
+      constants::FLAG_GLOSS => Some(self.gloss),
+      constants::FLAG_HAS_BONE_DIRECTIONS => Some(self.has_bone_directions as u32),
+      _ => self.unknown.iter().find(|x| x.0 == key).map(|x| x.1),
+    }
+  }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum SettingsValue {
+  // Key/value pairs.
+  Flags(Vec<(u32, u32)>),
+  // Pose text, padded to a multiple of four bytes.
+  Pose(Vec<u8>),
+  Raw(Vec<u32>),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct SettingsEntry {
+  pub option_type: u32,
+  pub opt_count: u32,
+  pub opt_info: u32,
+  pub value: SettingsValue,
+}
+
+// Files up to version 1.12 store the settings as an opaque block, kept in
+// `legacy`; later versions store a hash followed by a list of options.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct HeaderSettings {
+  pub hash: u32,
+  pub entries: Vec<SettingsEntry>,
+  pub legacy: Vec<u8>,
+}
+
+impl HeaderSettings {
+  pub fn entry(&self, option_type: u32) -> Option<&SettingsEntry> {
+    self.entries.iter().find(|x| x.option_type == option_type)
+  }
+}
+
 impl Default for Header {
   fn default() -> Header {
     Header {
@@ -84,7 +347,8 @@ impl Default for Header {
       machine: String::default(),
       user: String::default(),
       file: String::default(),
-      settings: String::default(),
+      settings: HeaderSettings::default(),
+      flags: HeaderFlags::default(),
       pose: String::default(),
     }
   }
@@ -0,0 +1,180 @@
+use super::types::{BoneWeight, Data, Mesh, Vertex};
+use std::collections::HashMap;
+
+pub struct WeldResult {
+  pub remap: Vec<u32>,
+  pub merged_count: usize,
+  pub seam_groups: Vec<Vec<u32>>,
+}
+
+struct Grid {
+  cell_size: f32,
+  cells: HashMap<(i64, i64, i64), Vec<u32>>,
+}
+
+impl Grid {
+  fn new(tolerance: f32) -> Grid {
+    Grid {
+      cell_size: tolerance.max(f32::EPSILON),
+      cells: HashMap::new(),
+    }
+  }
+
+  fn cell(&self, position: [f32; 3]) -> (i64, i64, i64) {
+    (
+      (position[0] / self.cell_size).floor() as i64,
+      (position[1] / self.cell_size).floor() as i64,
+      (position[2] / self.cell_size).floor() as i64,
+    )
+  }
+
+  fn find<F: Fn(u32) -> bool>(&self, position: [f32; 3], matches: F) -> Option<u32> {
+    let (x, y, z) = self.cell(position);
+    for dx in -1..=1 {
+      for dy in -1..=1 {
+        for dz in -1..=1 {
+          if let Some(bucket) = self.cells.get(&(x + dx, y + dy, z + dz)) {
+            if let Some(found) = bucket.iter().find(|i| matches(**i)) {
+              return Some(*found);
+            }
+          }
+        }
+      }
+    }
+    None
+  }
+
+  fn insert(&mut self, position: [f32; 3], index: u32) {
+    let cell = self.cell(position);
+    self.cells.entry(cell).or_default().push(index);
+  }
+}
+
+fn near(a: &[f32], b: &[f32], tolerance: f32) -> bool {
+  a.iter().zip(b).all(|(x, y)| (x - y).abs() <= tolerance)
+}
+
+fn weight_of(weights: &[BoneWeight], id: i16) -> f32 {
+  weights
+    .iter()
+    .filter(|x| x.id == id)
+    .map(|x| x.weight)
+    .sum()
+}
+
+fn same_weights(a: &[BoneWeight], b: &[BoneWeight], tolerance: f32) -> bool {
+  a.iter()
+    .chain(b)
+    .all(|x| (weight_of(a, x.id) - weight_of(b, x.id)).abs() <= tolerance)
+}
+
+fn same_vertex(a: &Vertex, b: &Vertex, tolerance: f32) -> bool {
+  near(&a.position, &b.position, tolerance)
+    && near(&a.normal, &b.normal, tolerance)
+    && a.color == b.color
+    && a.uv.len() == b.uv.len()
+    && a
+      .uv
+      .iter()
+      .zip(b.uv.iter())
+      .all(|(x, y)| near(x, y, tolerance))
+    && same_weights(&a.bone_weights, &b.bone_weights, tolerance)
+}
+
+pub fn seam_groups(mesh: &Mesh, tolerance: f32) -> Vec<Vec<u32>> {
+  let mesh = &*mesh.unpacked();
+  let mut grid = Grid::new(tolerance);
+  let mut groups: Vec<Vec<u32>> = vec![];
+  for (index, vertex) in mesh.vertices.iter().enumerate() {
+    let found = grid.find(vertex.position, |x| {
+      near(
+        &mesh.vertices[groups[x as usize][0] as usize].position,
+        &vertex.position,
+        tolerance,
+      )
+    });
+    match found {
+      Some(group) => groups[group as usize].push(index as u32),
+      None => {
+        grid.insert(vertex.position, groups.len() as u32);
+        groups.push(vec![index as u32]);
+      }
+    }
+  }
+  groups.into_iter().filter(|x| x.len() > 1).collect()
+}
+
+pub fn weld_mesh(mesh: &mut Mesh, tolerance: f32) -> WeldResult {
+  let buffered = mesh.unpack_buffers();
+  let mut grid = Grid::new(tolerance);
+  let mut vertices: Vec<Vertex> = vec![];
+  let mut remap = Vec::with_capacity(mesh.vertices.len());
+  let mut merged_count = 0;
+  for vertex in &mesh.vertices {
+    let found = grid.find(vertex.position, |x| {
+      same_vertex(&vertices[x as usize], vertex, tolerance)
+    });
+    match found {
+      Some(x) => {
+        vertices[x as usize].merged = true;
+        merged_count += 1;
+        remap.push(x);
+      }
+      None => {
+        let index = vertices.len() as u32;
+        grid.insert(vertex.position, index);
+        vertices.push(vertex.clone());
+        remap.push(index);
+      }
+    }
+  }
+  for face in mesh.faces.iter_mut() {
+    if let Some(x) = remap.get(*face as usize) {
+      *face = *x;
+    }
+  }
+  mesh.vertices = vertices;
+  let seam_groups = seam_groups(mesh, tolerance);
+  if buffered {
+    mesh.pack_buffers();
+  }
+  WeldResult {
+    remap,
+    merged_count,
+    seam_groups,
+  }
+}
+
+pub fn weld_model(data: &mut Data, tolerance: f32) -> Vec<WeldResult> {
+  data
+    .meshes
+    .iter_mut()
+    .map(|x| weld_mesh(x, tolerance))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::test_util;
+
+  #[test]
+  fn smooth_cube_welds_to_corners() {
+    let mut mesh = test_util::cube(true);
+    let result = weld_mesh(&mut mesh, 0.0001);
+    assert_eq!(mesh.vertices.len(), 8);
+    assert_eq!(result.merged_count, 16);
+    assert_eq!(mesh.faces.len(), 36);
+    assert!(mesh.faces.iter().all(|x| (*x as usize) < 8));
+  }
+
+  #[test]
+  fn hard_cube_keeps_sides_apart() {
+    let mut mesh = test_util::cube(false);
+    let result = weld_mesh(&mut mesh, 0.0001);
+    assert_eq!(mesh.vertices.len(), 24);
+    assert_eq!(result.merged_count, 0);
+    assert_eq!(result.seam_groups.len(), 8);
+    assert!(result.seam_groups.iter().all(|x| x.len() == 3));
+  }
+}